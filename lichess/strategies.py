@@ -62,12 +62,25 @@ class MinimalEngine(EngineWrapper):
         pass
 
 class Chevii(MinimalEngine):
+    process = None
+
     def search(self, board, timeleft, *args):
         time = min(3, max(0.1, timeleft / 60000))
         print(time)
         print(timeleft)
-        p = subprocess.Popen('./chevii --time ' + str(time) + ' -p "' + board.fen() + '"', shell=True, stdout=subprocess.PIPE, stderr=subprocess.STDOUT)
-        out = p.stdout.readlines()
+        # Keep one process per game, so the engine can reuse its previous searches
+        if self.process is None:
+            self.process = subprocess.Popen('./chevii', shell=True, stdin=subprocess.PIPE, stdout=subprocess.PIPE, universal_newlines=True)
+        self.process.stdin.write('go ' + str(time) + ' ' + board.fen() + '\n')
+        self.process.stdin.flush()
+        out = self.process.stdout.readline()
 
-        move = chess.Move.from_uci(out[0].decode('utf-8').replace("\n", ""))
+        move = chess.Move.from_uci(out.replace("\n", ""))
         return PlayResult(move, None)
+
+    def quit(self):
+        if self.process is not None:
+            self.process.stdin.write('quit\n')
+            self.process.stdin.flush()
+            self.process.wait()
+            self.process = None
@@ -0,0 +1,495 @@
+use crate::ai::draw::{Contempt, GameHistory};
+use crate::ai::engine::Engine;
+use crate::ai::searcher::{Limits, Report};
+use chess::{Board, ChessMove, MoveGen};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How much longer than the search time a worker may take to reply before it counts as dead
+const REPLY_MARGIN: Duration = Duration::from_secs(10);
+/// How long a worker may take for a search of a number of nodes, which has no time limit
+const NODES_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// What the coordinator asks a worker to do. Commands and replies are single lines of text.
+#[derive(Clone, Debug)]
+enum Command {
+    /// Search some of the root moves of `board`, and reply with a report.
+    /// `history` holds the positions of the game before `board` since the last irreversible move.
+    Search {
+        board: Board,
+        history: Vec<u64>,
+        limits: Limits,
+    },
+    NewGame,
+    Contempt(Contempt),
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Search {
+                board,
+                history,
+                limits,
+            } => write!(
+                f,
+                "search {} {} {} {} {}",
+                limits.time,
+                limits.nodes.map_or("-".to_string(), |n| n.to_string()),
+                join(&limits.search_moves, ","),
+                join(history, ","),
+                board
+            ),
+            Command::NewGame => write!(f, "newgame"),
+            Command::Contempt(contempt) => {
+                write!(f, "contempt {} {}", contempt.white, contempt.black)
+            }
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(6, ' ');
+        match parts.next() {
+            Some("search") => {
+                let time = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+                let nodes = match parts.next().ok_or(())? {
+                    "-" => None,
+                    nodes => Some(nodes.parse().map_err(|_| ())?),
+                };
+                let search_moves = split(parts.next().ok_or(())?, ',')?;
+                let history = split(parts.next().ok_or(())?, ',')?;
+                let board = Board::from_str(parts.next().ok_or(())?).map_err(|_| ())?;
+                Ok(Command::Search {
+                    board,
+                    history,
+                    limits: Limits {
+                        time,
+                        nodes,
+                        search_moves,
+                    },
+                })
+            }
+            Some("newgame") => Ok(Command::NewGame),
+            Some("contempt") => Ok(Command::Contempt(Contempt {
+                white: parts.next().ok_or(())?.parse().map_err(|_| ())?,
+                black: parts.next().ok_or(())?.parse().map_err(|_| ())?,
+            })),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `items` joined by `separator`, or `-` if there are none.
+fn join<T: fmt::Display>(items: &[T], separator: &str) -> String {
+    if items.is_empty() {
+        return "-".to_string();
+    }
+    items
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn split<T: FromStr>(items: &str, separator: char) -> Result<Vec<T>, ()> {
+    if items == "-" {
+        return Ok(Vec::new());
+    }
+    items
+        .split(separator)
+        .map(|m| m.parse().map_err(|_| ()))
+        .collect()
+}
+
+/// A worker's reply to a search: `report <best> <score> <depth> <nodes> <pv>`.
+fn format_report(report: &Report) -> String {
+    format!(
+        "report {} {} {} {} {}",
+        report.best,
+        report.score,
+        report.depth,
+        report.nodes,
+        join(&report.pv, " ")
+    )
+}
+
+fn parse_report(line: &str) -> Result<Report, ()> {
+    let mut parts = line.splitn(6, ' ');
+    if parts.next() != Some("report") {
+        return Err(());
+    }
+    let best = ChessMove::from_str(parts.next().ok_or(())?).map_err(|_| ())?;
+    let mut report = Report::unsearched(best);
+    report.score = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+    report.depth = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+    report.nodes = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+    report.pv = split(parts.next().ok_or(())?, ' ')?;
+    Ok(report)
+}
+
+fn invalid(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid message: {}", line),
+    )
+}
+
+/// The root moves of `board` allowed by `limits`, dealt out to `workers` workers in turn.
+/// Workers may get no moves if there are fewer moves than workers.
+fn share_moves(board: &Board, limits: &Limits, workers: usize) -> Vec<Vec<ChessMove>> {
+    let mut shares = vec![Vec::new(); workers];
+    for (i, mov) in MoveGen::new_legal(board)
+        .filter(|m| limits.allows(*m))
+        .enumerate()
+    {
+        shares[i % workers].push(mov);
+    }
+    shares
+}
+
+/// The report of the worker with the best score, with the nodes of all of them.
+fn merge(reports: Vec<Report>) -> Option<Report> {
+    let nodes = reports.iter().map(|r| r.nodes).sum();
+    let mut best = reports.into_iter().max_by_key(|r| r.score)?;
+    best.nodes = nodes;
+    Some(best)
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn send(&mut self, command: &Command) -> io::Result<()> {
+        writeln!(self.writer, "{}", command)
+    }
+
+    /// Wait for the report of a search, for at most `timeout`.
+    fn receive(&mut self, timeout: Duration) -> io::Result<Report> {
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        let mut line = String::new();
+        let read = self.reader.read_line(&mut line).map_err(|error| {
+            match error.kind() {
+                // What a timeout looks like, depending on the platform
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                    io::Error::new(io::ErrorKind::TimedOut, "Worker did not reply in time")
+                }
+                _ => error,
+            }
+        })?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        parse_report(line.trim_end()).map_err(|_| invalid(&line))
+    }
+}
+
+/// Runs one search across several worker processes, e.g. one per NUMA node:
+/// Each worker searches a share of the root moves with its own engine,
+/// and the best of their results is played.
+pub struct Coordinator {
+    workers: Vec<Connection>,
+    /// The game played so far, which the workers search with
+    game: GameHistory,
+}
+
+impl Coordinator {
+    /// Wait until `workers` workers have connected to `listener`.
+    pub fn accept(listener: &TcpListener, workers: usize) -> io::Result<Self> {
+        let workers = (0..workers)
+            .map(|_| {
+                let (stream, address) = listener.accept()?;
+                log::info!("Worker connected from {}", address);
+                Ok(Connection {
+                    reader: BufReader::new(stream.try_clone()?),
+                    writer: stream,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            workers,
+            game: GameHistory::default(),
+        })
+    }
+
+    /// Search `board` on all workers at the same time, each within `limits`.
+    /// Fails if a worker disconnects, or takes much longer than the time to reply.
+    /// Without any moves to search, the report is unsearched, like the engine's.
+    pub fn search(&mut self, board: Board, limits: &Limits) -> io::Result<Report> {
+        let history = self.game.before(&board).to_vec();
+        let shares = share_moves(&board, limits, self.workers.len());
+        if shares.iter().all(Vec::is_empty) {
+            return Ok(Report::unsearched(ChessMove::default()));
+        }
+        let busy = self
+            .workers
+            .iter_mut()
+            .zip(shares)
+            .filter(|(_, moves)| !moves.is_empty())
+            .map(|(worker, search_moves)| {
+                let limits = Limits {
+                    time: limits.time,
+                    nodes: limits.nodes,
+                    search_moves,
+                };
+                worker.send(&Command::Search {
+                    board,
+                    history: history.clone(),
+                    limits,
+                })?;
+                Ok(worker)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let deadline = Instant::now()
+            + match limits.nodes {
+                Some(_) => NODES_TIMEOUT,
+                None => Duration::from_secs_f32(limits.time) + REPLY_MARGIN,
+            };
+        let reports = busy
+            .into_iter()
+            .map(|worker| {
+                // A zero timeout would mean none at all
+                let timeout = deadline
+                    .saturating_duration_since(Instant::now())
+                    .max(Duration::from_millis(1));
+                worker.receive(timeout)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let report = merge(reports)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No reports from workers"))?;
+        self.game.played(&board, report.best);
+        log::info!(
+            "Workers searched {} nodes, best move {} ({})",
+            report.nodes,
+            report.best,
+            report.score
+        );
+        Ok(report)
+    }
+
+    /// Make all workers forget the previous game.
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.game.clear();
+        self.broadcast(&Command::NewGame)
+    }
+
+    pub fn set_contempt(&mut self, contempt: Contempt) -> io::Result<()> {
+        self.broadcast(&Command::Contempt(contempt))
+    }
+
+    fn broadcast(&mut self, command: &Command) -> io::Result<()> {
+        self.workers.iter_mut().try_for_each(|w| w.send(command))
+    }
+}
+
+/// Connect to the coordinator at `address`, and search with `engine`
+/// whatever it asks for until it disconnects.
+pub fn run_worker(address: &str, engine: &mut Engine) -> io::Result<()> {
+    serve(TcpStream::connect(address)?, |command| match command {
+        Command::Search {
+            board,
+            history,
+            limits,
+        } => Some(engine.search_position(board, &history, limits, &mut |_| ())),
+        Command::NewGame => {
+            engine.new_game();
+            None
+        }
+        Command::Contempt(contempt) => {
+            engine.settings_mut().contempt = contempt;
+            None
+        }
+    })
+}
+
+/// Answer the commands coming in on `stream` with `handle` until the stream is closed.
+fn serve(stream: TcpStream, mut handle: impl FnMut(Command) -> Option<Report>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let command = line.parse().map_err(|_| invalid(&line))?;
+        if let Some(report) = handle(command) {
+            writeln!(writer, "{}", format_report(&report))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_report, parse_report, serve, share_moves, Command, Coordinator};
+    use crate::ai::minimax::Pvs;
+    use crate::ai::ordering::Heuristics;
+    use crate::ai::searcher::{Limits, Report, Searcher, SharedState};
+    use crate::ai::settings::Settings;
+    use crate::ai::table::TransTable;
+    use chess::{Board, ChessMove, MoveGen, Square};
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_messages() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let mate = ChessMove::new(Square::D1, Square::D8, None);
+        let command = Command::Search {
+            board,
+            history: vec![board.get_hash(), u64::MAX],
+            limits: Limits {
+                time: 1.5,
+                nodes: Some(100),
+                search_moves: vec![mate, ChessMove::new(Square::G1, Square::F1, None)],
+            },
+        };
+        match command.to_string().parse() {
+            Ok(Command::Search {
+                board: b,
+                history,
+                limits,
+            }) => {
+                assert_eq!(b, board);
+                assert_eq!(history, [board.get_hash(), u64::MAX]);
+                assert_eq!(limits.time, 1.5);
+                assert_eq!(limits.nodes, Some(100));
+                assert_eq!(limits.search_moves[0], mate);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!("contempt 10 -20".parse::<Command>().is_ok());
+        assert!("search 1 - e2e4".parse::<Command>().is_err());
+        assert!("search 1 - - - 8/8/8/8/8/8/8/k6K w - - 0 1"
+            .parse::<Command>()
+            .is_ok());
+
+        let mut report = Report::unsearched(mate);
+        report.score = 999998;
+        report.depth = 3;
+        report.nodes = 1234;
+        assert_eq!(parse_report(&format_report(&report)), Ok(report));
+    }
+
+    #[test]
+    fn test_share_moves() {
+        let board = Board::default();
+        let limits = Limits {
+            time: 0.0,
+            nodes: None,
+            search_moves: Vec::new(),
+        };
+        let shares = share_moves(&board, &limits, 3);
+        assert_eq!(shares.iter().map(Vec::len).collect::<Vec<_>>(), [7, 7, 6]);
+        let mut all = shares.concat();
+        all.sort_by_key(|m| m.to_string());
+        let mut legal = MoveGen::new_legal(&board).collect::<Vec<_>>();
+        legal.sort_by_key(|m| m.to_string());
+        assert_eq!(all, legal);
+    }
+
+    #[test]
+    fn test_distributed_search() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let workers = (0..3)
+            .map(|_| {
+                thread::spawn(move || {
+                    let state = SharedState {
+                        table: Arc::new(TransTable::new()),
+                        heuristics: Arc::new(Heuristics::new()),
+                        tree: None,
+                    };
+                    let mut histories = Vec::new();
+                    serve(
+                        TcpStream::connect(address).unwrap(),
+                        |command| match command {
+                            Command::Search {
+                                board,
+                                history,
+                                limits,
+                            } => {
+                                assert!(!limits.search_moves.is_empty());
+                                let settings = Settings::default();
+                                let report = Pvs.search(
+                                    &state,
+                                    board,
+                                    &history,
+                                    settings,
+                                    limits,
+                                    &mut |_| (),
+                                );
+                                histories.push(history);
+                                Some(report)
+                            }
+                            _ => None,
+                        },
+                    )
+                    .unwrap();
+                    histories
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut coordinator = Coordinator::accept(&listener, 3).unwrap();
+        let limits = Limits {
+            time: 0.0,
+            nodes: Some(2000),
+            search_moves: Vec::new(),
+        };
+        let report = coordinator.search(board, &limits).unwrap();
+        assert_eq!(report.best, ChessMove::new(Square::D1, Square::D8, None));
+        coordinator.new_game().unwrap();
+
+        // The positions of the game since the last irreversible move go with the next search
+        let first = Board::from_str("6k1/8/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let report = coordinator.search(first, &limits).unwrap();
+        let played = first.make_move_new(report.best);
+        let reply = MoveGen::new_legal(&played)
+            .find(|m| played.piece_on(m.get_dest()).is_none())
+            .unwrap();
+        let second = played.make_move_new(reply);
+        coordinator.search(second, &limits).unwrap();
+
+        // Nothing to search when mated
+        let mated = Board::from_str("3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 1 1").unwrap();
+        let report = coordinator.search(mated, &limits).unwrap();
+        assert_eq!(report.best, ChessMove::default());
+
+        drop(coordinator);
+        for worker in workers {
+            let histories = worker.join().unwrap();
+            assert_eq!(histories[0], []);
+            assert_eq!(histories[1], []);
+            assert_eq!(histories[2], [first.get_hash(), played.get_hash()]);
+        }
+    }
+
+    #[test]
+    fn test_dead_worker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let worker = thread::spawn(move || {
+            // Disconnects after the first command instead of replying
+            let stream = TcpStream::connect(address).unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+        });
+        let mut coordinator = Coordinator::accept(&listener, 1).unwrap();
+        let limits = Limits {
+            time: 0.1,
+            nodes: None,
+            search_moves: Vec::new(),
+        };
+        assert!(coordinator.search(Board::default(), &limits).is_err());
+        worker.join().unwrap();
+    }
+}
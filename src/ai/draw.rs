@@ -0,0 +1,287 @@
+use chess::{Board, ChessMove, Color, MoveGen, Piece, ALL_COLORS, EMPTY};
+
+/// Which score a draw is worth, depending on which side the engine plays, in the units
+/// of the evaluation (NNUE units, or about centipawns with the classical evaluation).
+/// A positive contempt makes the engine avoid draws, a negative one makes it seek them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Contempt {
+    pub white: i32,
+    pub black: i32,
+}
+
+impl Contempt {
+    /// Score of a draw for the side to move on `board`, when the engine plays `engine`.
+    pub fn draw_score(&self, board: &Board, engine: Color) -> i32 {
+        let contempt = match engine {
+            Color::White => self.white,
+            Color::Black => self.black,
+        };
+        if board.side_to_move() == engine {
+            -contempt
+        } else {
+            contempt
+        }
+    }
+}
+
+/// Follows the positions of a game from the moves played and the positions searched next,
+/// for detecting repetitions and the fifty-move rule in the next search. A position that
+/// does not follow from the last move played starts a new game.
+#[derive(Default)]
+pub struct GameHistory {
+    /// Positions of this game since the last irreversible move, before the next search
+    history: Vec<u64>,
+    /// The position after the last move played
+    last: Option<Board>,
+}
+
+impl GameHistory {
+    /// The positions played before `board` since the last irreversible move,
+    /// where `board` is the position searched next.
+    pub fn before(&mut self, board: &Board) -> &[u64] {
+        match self.last {
+            Some(last)
+                if MoveGen::new_legal(&last)
+                    .any(|m| last.make_move_new(m).get_hash() == board.get_hash()) =>
+            {
+                self.push(&last, board)
+            }
+            _ => self.history.clear(),
+        }
+        &self.history
+    }
+
+    /// `mov` was played on `board`.
+    pub fn played(&mut self, board: &Board, mov: ChessMove) {
+        let after = board.make_move_new(mov);
+        self.push(board, &after);
+        self.last = Some(after);
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.last = None;
+    }
+
+    fn push(&mut self, before: &Board, after: &Board) {
+        if is_irreversible(before, after) {
+            self.history.clear();
+        } else {
+            self.history.push(before.get_hash());
+        }
+    }
+}
+
+/// The positions leading to a node in the search tree, used to detect repetitions
+/// and the fifty-move rule. Positions before the root are taken from the game history.
+pub struct Line<'a> {
+    hash: u64,
+    /// Plies since the last capture or pawn move
+    clock: usize,
+    /// Plies since the root
+    ply: usize,
+    /// The move leading to the position at the end of this line
+    mov: Option<ChessMove>,
+    prev: Option<&'a Line<'a>>,
+    /// Positions played in the game before the root, since the last capture or pawn move
+    history: &'a [u64],
+}
+
+impl<'a> Line<'a> {
+    pub fn root(board: &Board, history: &'a [u64]) -> Self {
+        Self {
+            hash: board.get_hash(),
+            clock: history.len(),
+            ply: 0,
+            mov: None,
+            prev: None,
+            history,
+        }
+    }
+
+    /// The line after playing `mov` on `board` (the position at the end of this line).
+    pub fn child(&'a self, board: &Board, mov: ChessMove, after: &Board) -> Self {
+        let irreversible = board.piece_on(mov.get_source()) == Some(Piece::Pawn)
+            || board.piece_on(mov.get_dest()).is_some();
+        Self {
+            hash: after.get_hash(),
+            clock: if irreversible { 0 } else { self.clock + 1 },
+            ply: self.ply + 1,
+            mov: Some(mov),
+            prev: Some(self),
+            history: self.history,
+        }
+    }
+
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    pub fn last_move(&self) -> Option<ChessMove> {
+        self.mov
+    }
+
+    /// If the position at the end of this line is drawn by rule, ignoring stalemate.
+    pub fn is_draw(&self, board: &Board) -> bool {
+        (self.clock >= 100 && board.checkers() == &EMPTY)
+            || self.is_repetition()
+            || insufficient_material(board)
+    }
+
+    /// Any earlier occurrence of the position counts; repeating once
+    /// can always be repeated again by the side that wants the draw.
+    fn is_repetition(&self) -> bool {
+        let mut line = self;
+        let mut plies = 0;
+        while plies < self.clock {
+            match line.prev {
+                Some(prev) => line = prev,
+                None => {
+                    // Continue into the game history, which ends right before the root
+                    let history = line.history;
+                    return (1..=self.clock - plies)
+                        .filter(|i| (plies + i) % 2 == 0)
+                        .any(|i| history[history.len() - i] == self.hash);
+                }
+            }
+            plies += 1;
+            if plies % 2 == 0 && line.hash == self.hash {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// If neither side can possibly checkmate.
+pub fn insufficient_material(board: &Board) -> bool {
+    let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    if heavy != EMPTY {
+        return false;
+    }
+    let knights = board.pieces(Piece::Knight);
+    let bishops = board.pieces(Piece::Bishop);
+    match (knights | bishops).popcnt() {
+        0 | 1 => true,
+        // Only bishops, one per side, on same-coloured squares
+        2 if knights == &EMPTY => {
+            let dark = bishops & chess::BitBoard(0xAA55_AA55_AA55_AA55);
+            ALL_COLORS
+                .iter()
+                .all(|c| (board.color_combined(*c) & bishops).popcnt() == 1)
+                && (dark == EMPTY || dark == *bishops)
+        }
+        _ => false,
+    }
+}
+
+/// If the move leading from `before` to `after` resets the fifty-move clock.
+pub fn is_irreversible(before: &Board, after: &Board) -> bool {
+    before.pieces(Piece::Pawn) != after.pieces(Piece::Pawn)
+        || before.combined().popcnt() != after.combined().popcnt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{insufficient_material, GameHistory, Line};
+    use chess::{Board, ChessMove, Square};
+    use std::str::FromStr;
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        assert!(insufficient_material(&board(
+            "8/8/4k3/8/8/2K5/8/8 w - - 0 1"
+        )));
+        assert!(insufficient_material(&board(
+            "8/8/4k3/8/8/2KN4/8/8 w - - 0 1"
+        )));
+        assert!(insufficient_material(&board(
+            "8/8/2k1b3/8/8/2KB4/8/8 w - - 0 1"
+        )));
+        assert!(!insufficient_material(&board(
+            "8/8/4kb2/8/8/2KB4/8/8 w - - 0 1"
+        )));
+        assert!(!insufficient_material(&board(
+            "8/8/4k3/8/8/2KNN3/8/8 w - - 0 1"
+        )));
+        assert!(!insufficient_material(&board(
+            "8/8/4k3/8/8/2KP4/8/8 w - - 0 1"
+        )));
+    }
+
+    #[test]
+    fn test_repetition() {
+        let start = board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let moves = [
+            (Square::A1, Square::A2),
+            (Square::E8, Square::D8),
+            (Square::A2, Square::A1),
+            (Square::D8, Square::E8),
+        ];
+
+        // Repetition within the searched line
+        let root = Line::root(&start, &[]);
+        let mut boards = vec![start];
+        for (from, to) in &moves {
+            boards.push(
+                boards
+                    .last()
+                    .unwrap()
+                    .make_move_new(ChessMove::new(*from, *to, None)),
+            );
+        }
+        let l1 = root.child(
+            &boards[0],
+            ChessMove::new(moves[0].0, moves[0].1, None),
+            &boards[1],
+        );
+        let l2 = l1.child(
+            &boards[1],
+            ChessMove::new(moves[1].0, moves[1].1, None),
+            &boards[2],
+        );
+        let l3 = l2.child(
+            &boards[2],
+            ChessMove::new(moves[2].0, moves[2].1, None),
+            &boards[3],
+        );
+        let l4 = l3.child(
+            &boards[3],
+            ChessMove::new(moves[3].0, moves[3].1, None),
+            &boards[4],
+        );
+        assert!(!l3.is_draw(&boards[3]));
+        assert!(l4.is_draw(&boards[4]));
+
+        // Repetition of a position played earlier in the game
+        let history = boards[..4].iter().map(|b| b.get_hash()).collect::<Vec<_>>();
+        assert!(Line::root(&boards[4], &history).is_draw(&boards[4]));
+        assert!(!Line::root(&boards[4], &history[1..]).is_draw(&boards[4]));
+    }
+
+    #[test]
+    fn test_game_history() {
+        let start = board("4k3/1p6/8/8/8/8/8/R3K3 w - - 0 1");
+        let mut game = GameHistory::default();
+        assert!(game.before(&start).is_empty());
+
+        game.played(&start, ChessMove::new(Square::A1, Square::B1, None));
+        let played = start.make_move_new(ChessMove::new(Square::A1, Square::B1, None));
+        let replied = played.make_move_new(ChessMove::new(Square::E8, Square::D8, None));
+        assert_eq!(game.before(&replied), [start.get_hash(), played.get_hash()]);
+
+        // A capture makes the earlier positions unreachable
+        game.played(&replied, ChessMove::new(Square::B1, Square::B7, None));
+        let checked = replied.make_move_new(ChessMove::new(Square::B1, Square::B7, None));
+        let replied = checked.make_move_new(ChessMove::new(Square::D8, Square::E8, None));
+        assert_eq!(game.before(&replied), [checked.get_hash()]);
+
+        // A position that does not follow from the last move starts a new game
+        game.played(&replied, ChessMove::new(Square::B7, Square::B1, None));
+        assert!(game.before(&start).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::ai::draw::GameHistory;
+use crate::ai::ordering::Heuristics;
+use crate::ai::searcher::{Info, Limits, Report, Searcher, SharedState};
+use crate::ai::settings::Settings;
+use crate::ai::statistics::Stat;
+use crate::ai::table::TransTable;
+use crate::ai::threat::{self, Threat};
+use crate::ai::tree::SearchTree;
+use crate::ai::{minimax, nnue};
+use chess::{Board, BoardStatus, ChessMove, MoveGen};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A chess engine playing a single game.
+/// Keeps the transposition table (including the NNUE cache) and move ordering
+/// heuristics between moves, so each search starts with what was learned
+/// during the previous ones.
+pub struct Engine {
+    table: Arc<TransTable>,
+    heuristics: Arc<Heuristics>,
+    /// The game played so far
+    game: GameHistory,
+    settings: Settings,
+    /// The tree of the last search, if it is recorded
+    tree: Option<Arc<SearchTree>>,
+    searcher: Box<dyn Searcher>,
+}
+
+impl Engine {
+    /// Calculate the best possible move, using `time` amount of time.
+    /// Actual time spent will be slightly higher (maybe about 100ms? unmeasured).
+    ///
+    /// If `board` follows from the engine's previous move, the game so far is used
+    /// to detect repetitions and the fifty-move rule.
+    pub fn best_move(&mut self, board: Board, time: f32) -> ChessMove {
+        let limits = Limits {
+            time,
+            nodes: self.settings.deterministic.map(|d| d.nodes),
+            search_moves: Vec::new(),
+        };
+        self.search(board, limits, &mut |_| ()).best
+    }
+
+    /// Like [`Engine::best_move`], but with the full report and progress passed to `info`.
+    pub fn search(&mut self, board: Board, limits: Limits, info: &mut dyn FnMut(&Info)) -> Report {
+        let history = self.game.before(&board).to_vec();
+        let report = self.search_position(board, &history, limits, info);
+        if board.legal(report.best) {
+            self.game.played(&board, report.best);
+        }
+        report
+    }
+
+    /// Search `board`, reached through the positions in `history` since the last
+    /// irreversible move, without following the game: the moves played before
+    /// are not used, and the result is not played.
+    pub fn search_position(
+        &mut self,
+        board: Board,
+        history: &[u64],
+        limits: Limits,
+        info: &mut dyn FnMut(&Info),
+    ) -> Report {
+        if self.settings.shortcuts {
+            if let Some(mov) = obvious_move(&board).filter(|m| limits.allows(*m)) {
+                log::info!("Playing obvious move {}", mov);
+                let mut report = Report::unsearched(mov);
+                if board.make_move_new(mov).status() == BoardStatus::Checkmate {
+                    // So it can be compared with searched moves
+                    report.score = minimax::WIN;
+                }
+                return report;
+            }
+        }
+
+        if let Some(tree) = &self.tree {
+            tree.clear();
+        }
+        let state = SharedState {
+            table: self.table.clone(),
+            heuristics: self.heuristics.clone(),
+            tree: self.tree.clone(),
+        };
+        log::debug!("Searching with {}", self.searcher.name());
+        let report = self
+            .searcher
+            .search(&state, board, history, self.settings, limits, info);
+        Stat::log();
+        report
+    }
+
+    /// What the opponent threatens on `board`, found by letting the side to move pass,
+    /// compared to `report`, the result of [`Engine::search`] on `board`.
+    /// Uses the current searcher and table, but ignores the game history
+    /// and does not change the game state.
+    pub fn threat(&mut self, board: Board, report: &Report, limits: Limits) -> Option<Threat> {
+        let state = SharedState {
+            table: self.table.clone(),
+            heuristics: self.heuristics.clone(),
+            tree: None,
+        };
+        threat::find_threat(
+            &mut *self.searcher,
+            &state,
+            board,
+            report,
+            self.settings,
+            limits,
+        )
+    }
+
+    /// Use a different search algorithm from now on.
+    pub fn set_searcher(&mut self, searcher: Box<dyn Searcher>) {
+        self.searcher = searcher;
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    /// Forget everything learned so far; call this before starting a different game.
+    pub fn new_game(&mut self) {
+        self.table = Arc::new(TransTable::new());
+        self.heuristics = Arc::new(Heuristics::new());
+        self.game.clear();
+    }
+
+    /// Record the tree of each search up to `max_ply` plies from the root, for debugging.
+    /// This slows the search down a lot.
+    pub fn record_tree(&mut self, max_ply: usize) {
+        self.tree = Some(Arc::new(SearchTree::new(max_ply)));
+    }
+
+    /// The tree of the last search, if [`Engine::record_tree`] was called.
+    pub fn tree(&self) -> Option<&SearchTree> {
+        self.tree.as_deref()
+    }
+
+    /// Save the transposition table, e.g. to continue a long analysis later.
+    pub fn save_table(&self, path: &Path) -> io::Result<()> {
+        self.table.save(path)
+    }
+
+    /// Replace the transposition table with one saved earlier.
+    pub fn load_table(&mut self, path: &Path) -> io::Result<()> {
+        self.table = Arc::new(TransTable::load(path)?);
+        Ok(())
+    }
+
+    /// Combine a saved transposition table with the current one,
+    /// e.g. one from another machine analysing the same opening.
+    pub fn merge_table(&self, path: &Path) -> io::Result<()> {
+        self.table.merge(path)
+    }
+
+    pub fn new() -> Self {
+        if !nnue::init() {
+            log::warn!("Could not load model.nnue, using the classical evaluation");
+        }
+        Self {
+            table: Arc::new(TransTable::new()),
+            heuristics: Arc::new(Heuristics::new()),
+            game: GameHistory::default(),
+            settings: Settings::default(),
+            tree: None,
+            searcher: Box::new(minimax::Pvs),
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A move that needs no search: the only legal move, or one that mates at once.
+/// There are no endgame tablebases to look up other decided positions.
+fn obvious_move(board: &Board) -> Option<ChessMove> {
+    let mut moves = MoveGen::new_legal(board);
+    if moves.len() == 1 {
+        return moves.next();
+    }
+    moves.find(|mov| board.make_move_new(*mov).status() == BoardStatus::Checkmate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{obvious_move, Engine};
+    use crate::ai::mcts::Mcts;
+    use crate::ai::searcher::Limits;
+    use chess::{Board, ChessMove, Square};
+    use std::str::FromStr;
+    use std::time::Instant;
+
+    #[test]
+    fn test_obvious_move() {
+        let only = Board::from_str("k7/8/8/8/8/8/1R6/KR6 b - - 0 1").unwrap();
+        assert_eq!(
+            obvious_move(&only),
+            Some(ChessMove::new(Square::A8, Square::A7, None))
+        );
+        let mate = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        assert_eq!(
+            obvious_move(&mate),
+            Some(ChessMove::new(Square::D1, Square::D8, None))
+        );
+        assert_eq!(obvious_move(&Board::default()), None);
+    }
+
+    #[test]
+    fn test_shortcuts() {
+        // Any searcher answers a forced move at once
+        let only = Board::from_str("k7/8/8/8/8/8/1R6/KR6 b - - 0 1").unwrap();
+        let limits = Limits {
+            time: 30.0,
+            nodes: None,
+            search_moves: Vec::new(),
+        };
+        let mut engine = Engine::new();
+        engine.set_searcher(Box::new(Mcts));
+        let start = Instant::now();
+        let report = engine.search(only, limits.clone(), &mut |_| ());
+        assert!(start.elapsed().as_secs_f32() < 1.0);
+        assert_eq!(report.best, ChessMove::new(Square::A8, Square::A7, None));
+        assert_eq!(report.depth, 0);
+
+        // Unless they are turned off
+        engine.settings_mut().shortcuts = false;
+        let limits = Limits {
+            time: 0.0,
+            nodes: Some(100),
+            ..limits
+        };
+        assert!(engine.search(only, limits, &mut |_| ()).depth > 0);
+    }
+}
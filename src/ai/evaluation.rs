@@ -1,17 +1,111 @@
+use crate::ai::pawns::{self, PawnTable};
+use crate::ai::settings::Evaluator;
 use crate::ai::statistics::Stat;
 use crate::ai::table::{NNUEEntry, TransTable};
-use crate::ai::{get_player_back_rank, get_player_pawn_bits, nnue};
+use crate::ai::{get_player_back_rank, get_player_pawn_bits, nnue, pst};
 use chess::CastleRights::NoRights;
 use chess::{
-    BitBoard, Board, CastleRights, ChessMove, Color, Piece, Square, ALL_PIECES, NUM_PIECES,
+    get_adjacent_files, get_bishop_moves, get_file, get_knight_moves, get_pawn_attacks, get_rank,
+    get_rook_moves, BitBoard, Board, CastleRights, ChessMove, Color, File, Piece, Rank, Square,
+    EMPTY, NUM_PIECES,
 };
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
 
 const PIECE_VALUE: [i32; NUM_PIECES] = [100, 300, 300, 500, 900, 99900];
 const CONSIDER_VALUE: [i32; NUM_PIECES] = [20, 60, 60, 100, 250, 9990];
 const CASTLE_BONUS: i32 = 8;
 const CHECK_PENALTY: i32 = 15;
+/// Contribution of each piece to the game phase; the phase is full with all pieces on the board
+const PHASE_WEIGHT: [i32; NUM_PIECES] = [0, 1, 1, 2, 4, 0];
+const FULL_PHASE: i32 = 24;
+/// Per square a piece can move to that no enemy pawn attacks, in the order of [`chess::ALL_PIECES`]
+const MOBILITY: [Tapered; NUM_PIECES] = [
+    Tapered::new(0, 0),
+    Tapered::new(4, 4),
+    Tapered::new(5, 5),
+    Tapered::new(2, 4),
+    Tapered::new(1, 2),
+    Tapered::new(0, 0),
+];
+/// Squares a piece usually has; fewer make its mobility score negative
+const MOBILITY_BASE: [i32; NUM_PIECES] = [0, 4, 6, 7, 13, 0];
+/// A knight or bishop defended by a pawn, where no enemy pawn can ever attack it
+const OUTPOST: [Tapered; 2] = [Tapered::new(30, 20), Tapered::new(15, 10)];
+const ROOK_OPEN_FILE: Tapered = Tapered::new(25, 10);
+const ROOK_SEMI_OPEN_FILE: Tapered = Tapered::new(12, 6);
+const ROOK_SEVENTH: Tapered = Tapered::new(20, 30);
+/// A rook next to a king that cannot castle any more, with hardly any room
+const TRAPPED_ROOK: Tapered = Tapered::new(-50, -10);
+/// A bishop that took the a7 or h7 pawn and is shut in by the pawn next to it
+const TRAPPED_BISHOP: Tapered = Tapered::new(-100, -100);
 
-pub(super) fn eval_board(board: &Board, table: &TransTable) -> i32 {
+/// A middlegame and an endgame score, blended by the game phase.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct Tapered {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Tapered {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// The score at `phase`, from 0 for a bare endgame to [`FULL_PHASE`] at the start.
+    fn blend(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (FULL_PHASE - phase)) / FULL_PHASE
+    }
+}
+
+impl Add for Tapered {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Tapered {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Mul<i32> for Tapered {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self {
+        Self::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl Neg for Tapered {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.mg, -self.eg)
+    }
+}
+
+/// Score of `board` for the side to move, with `evaluator`.
+/// Falls back to [`eval_static`] if the NNUE network could not be loaded.
+pub(super) fn eval_board(board: &Board, table: &TransTable, evaluator: Evaluator) -> i32 {
+    match evaluator {
+        Evaluator::Nnue if nnue::is_ready() => eval_nnue(board, table),
+        _ => eval_static(board, Some(table.pawns())),
+    }
+}
+
+/// The NNUE evaluation, cached in the table.
+fn eval_nnue(board: &Board, table: &TransTable) -> i32 {
     let hash = board.get_hash();
     match table.get_nnue(hash) {
         Some(eval) => {
@@ -30,60 +124,209 @@ pub(super) fn eval_board(board: &Board, table: &TransTable) -> i32 {
     }
 }
 
-#[allow(unused)]
-pub(super) fn eval_static(board: &Board) -> i32 {
+/// The classical evaluation: Material and piece-square tables plus a few positional terms,
+/// each with a middlegame and an endgame value, blended by how much material is left.
+/// Needs no network, and is a baseline to compare the NNUE evaluation against.
+/// The pawn structure is cached in `pawn_table`, if given.
+pub(super) fn eval_static(board: &Board, pawn_table: Option<&PawnTable>) -> i32 {
     let player = board.side_to_move();
-    let player_eval = eval_all(board, player);
-    let opponent_eval = eval_all(board, !player);
-    (player_eval - opponent_eval)
+    let pawns = match player {
+        Color::White => pawns::eval_pawns(board, pawn_table),
+        Color::Black => -pawns::eval_pawns(board, pawn_table),
+    };
+    let score = eval_all(board, player) - eval_all(board, !player) + pawns;
+    score.blend(game_phase(board))
+}
+
+/// From [`FULL_PHASE`] with all pieces on the board down to 0 with only kings and pawns.
+fn game_phase(board: &Board) -> i32 {
+    let phase = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .iter()
+        .map(|piece| board.pieces(*piece).popcnt() as i32 * PHASE_WEIGHT[piece.to_index()])
+        .sum::<i32>();
+    // Promotions can add more
+    phase.min(FULL_PHASE)
 }
 
-fn eval_all(board: &Board, player: Color) -> i32 {
-    eval_material(board, player)
+fn eval_all(board: &Board, player: Color) -> Tapered {
+    eval_pieces(board, player)
         + eval_castling(board, player)
         + eval_king(board, player)
         + eval_bishop(board, player)
+        + eval_mobility(board, player)
+        + eval_outposts(board, player)
+        + eval_rooks(board, player)
+        + eval_trapped_bishops(board, player)
 }
 
-fn eval_material(board: &Board, player: Color) -> i32 {
-    let mut total = 0;
-    let max = board.color_combined(player);
-
-    for piece in ALL_PIECES {
-        let value = piece_value(piece) as u32;
-        let bits = board.pieces(piece);
-        total += ((max & bits).popcnt() * value) as i32;
+/// Material and piece-square tables.
+fn eval_pieces(board: &Board, player: Color) -> Tapered {
+    let mut total = Tapered::default();
+    for square in *board.color_combined(player) {
+        let piece = board.piece_on(square).unwrap();
+        total += pst::piece_square(piece, player, square);
     }
-
     total
 }
 
-fn eval_castling(board: &Board, player: Color) -> i32 {
-    match board.castle_rights(player) {
+/// Castling keeps the king safe, which only matters while there are pieces to attack it.
+fn eval_castling(board: &Board, player: Color) -> Tapered {
+    let bonus = match board.castle_rights(player) {
         NoRights => 0,
         CastleRights::KingSide | CastleRights::QueenSide => CASTLE_BONUS,
         CastleRights::Both => CASTLE_BONUS * 2,
-    }
+    };
+    Tapered::new(bonus, 0)
 }
 
-fn eval_king(board: &Board, player: Color) -> i32 {
-    let mut score = 0;
+fn eval_king(board: &Board, player: Color) -> Tapered {
+    let mut score = Tapered::default();
     if board.side_to_move() == player && board.checkers().popcnt() != 0 {
-        score -= CHECK_PENALTY;
+        score += Tapered::new(-CHECK_PENALTY, -CHECK_PENALTY);
     }
 
+    // Shelter is for the middlegame, the king should come out in the endgame
     let bb_around_king = get_king_adjacent_squares(board.king_square(player));
     let pieces_around_king = bb_around_king & board.color_combined(player);
-    score += (pieces_around_king.popcnt() * 8) as i32;
+    score += Tapered::new((pieces_around_king.popcnt() * 8) as i32, 0);
+
+    score
+}
+
+fn eval_bishop(board: &Board, player: Color) -> Tapered {
+    let pair = ((board.color_combined(player) & board.pieces(Piece::Bishop)).popcnt() > 1) as i32;
+    Tapered::new(pair * 20, pair * 40)
+}
+
+/// Squares each piece can move to without being taken by a pawn, and rooks without room.
+fn eval_mobility(board: &Board, player: Color) -> Tapered {
+    let own = board.color_combined(player);
+    let occupied = board.combined();
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!player);
+    let unsafe_squares = enemy_pawns.fold(EMPTY, |attacked, square| {
+        attacked | get_pawn_attacks(square, !player, !EMPTY)
+    });
+    let mut score = Tapered::default();
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for square in board.pieces(piece) & own {
+            let moves = match piece {
+                Piece::Knight => get_knight_moves(square),
+                Piece::Bishop => get_bishop_moves(square, *occupied),
+                Piece::Rook => get_rook_moves(square, *occupied),
+                _ => get_bishop_moves(square, *occupied) | get_rook_moves(square, *occupied),
+            };
+            let count = (moves & !own & !unsafe_squares).popcnt() as i32;
+            score += MOBILITY[piece.to_index()] * (count - MOBILITY_BASE[piece.to_index()]);
+            if piece == Piece::Rook && count <= 3 && is_rook_trapped(board, player, square) {
+                score += TRAPPED_ROOK;
+            }
+        }
+    }
+    score
+}
+
+/// If the rook on `square` is shut in on the king's side by its own king, which cannot castle.
+fn is_rook_trapped(board: &Board, player: Color, square: Square) -> bool {
+    let king = board.king_square(player);
+    if board.castle_rights(player) != NoRights
+        || king.get_rank() != square.get_rank()
+        || king.get_rank() != player.to_my_backrank()
+    {
+        return false;
+    }
+    let (king_file, rook_file) = (king.get_file(), square.get_file());
+    (king_file >= File::E && rook_file > king_file)
+        || (king_file <= File::D && rook_file < king_file)
+}
+
+/// Knights and bishops on the opponent's half, defended by a pawn and safe from enemy pawns.
+fn eval_outposts(board: &Board, player: Color) -> Tapered {
+    let own_pawns = board.pieces(Piece::Pawn) & board.color_combined(player);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!player);
+    let mut score = Tapered::default();
+    for (i, piece) in [Piece::Knight, Piece::Bishop].iter().enumerate() {
+        for square in board.pieces(*piece) & board.color_combined(player) {
+            let rank = pawns::relative_rank(square, player);
+            let defended = get_pawn_attacks(square, !player, own_pawns) != EMPTY;
+            let attackable = enemy_pawns
+                & get_adjacent_files(square.get_file())
+                & pawns::ahead_of(square, player)
+                != EMPTY;
+            if (3..=5).contains(&rank) && defended && !attackable {
+                score += OUTPOST[i];
+            }
+        }
+    }
+    score
+}
 
+/// Rooks on files without pawns, or without pawns of their own, and on the seventh rank.
+fn eval_rooks(board: &Board, player: Color) -> Tapered {
+    let pawns = board.pieces(Piece::Pawn);
+    let own_pawns = pawns & board.color_combined(player);
+    let enemy_pawns = pawns & board.color_combined(!player);
+    let seventh = get_rank(match player {
+        Color::White => Rank::Seventh,
+        Color::Black => Rank::Second,
+    });
+    let mut score = Tapered::default();
+    for square in board.pieces(Piece::Rook) & board.color_combined(player) {
+        let file = get_file(square.get_file());
+        if pawns & file == EMPTY {
+            score += ROOK_OPEN_FILE;
+        } else if own_pawns & file == EMPTY {
+            score += ROOK_SEMI_OPEN_FILE;
+        }
+        // Only worth it with pawns to attack there, or the enemy king cut off behind it
+        let king_cut_off = board.king_square(!player).get_rank() == (!player).to_my_backrank();
+        if BitBoard::from_square(square) & seventh != EMPTY
+            && (enemy_pawns & seventh != EMPTY || king_cut_off)
+        {
+            score += ROOK_SEVENTH;
+        }
+    }
     score
 }
 
-fn eval_bishop(board: &Board, player: Color) -> i32 {
-    ((board.color_combined(player) & board.pieces(Piece::Bishop)).popcnt() > 1) as i32 * 20
+/// Bishops that took the a7 or h7 pawn (a2 or h2 for black) and were shut in by a pawn on b6 or g6.
+fn eval_trapped_bishops(board: &Board, player: Color) -> Tapered {
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!player);
+    let bishops = board.pieces(Piece::Bishop) & board.color_combined(player);
+    let traps = match player {
+        Color::White => [(Square::A7, Square::B6), (Square::H7, Square::G6)],
+        Color::Black => [(Square::A2, Square::B3), (Square::H2, Square::G3)],
+    };
+    let mut score = Tapered::default();
+    for (bishop, pawn) in traps {
+        if bishops & BitBoard::from_square(bishop) != EMPTY
+            && enemy_pawns & BitBoard::from_square(pawn) != EMPTY
+        {
+            score += TRAPPED_BISHOP;
+        }
+    }
+    score
 }
 
 pub(super) fn eval_move(board: &Board, table: &TransTable, cmove: ChessMove) -> i32 {
+    let mut value = eval_move_static(board, cmove);
+
+    let applied = board.make_move_new(cmove);
+    // We've had this move before during ID, so there's a very high chance it's good
+    if let Some(entry) = table.get(applied.get_hash()) {
+        Stat::TableEvalHits.inc();
+        value += 1024 * entry.depth_of_search as i32 * entry.depth_of_score as i32;
+    }
+
+    // Checking is often a good idea
+    if applied.checkers().0 != 0 {
+        value += 50;
+    }
+
+    value
+}
+
+/// The part of [`eval_move`] that does not need to make the move; cheap enough for every node.
+pub(super) fn eval_move_static(board: &Board, cmove: ChessMove) -> i32 {
     let mut value = 0;
     let moving_piece = board.piece_on(cmove.get_source()).unwrap();
     let captured_piece = board.piece_on(cmove.get_dest());
@@ -128,19 +371,23 @@ pub(super) fn eval_move(board: &Board, table: &TransTable, cmove: ChessMove) ->
         value -= 25;
     }
 
-    let applied = board.make_move_new(cmove);
-    // We've had this move before during ID, so there's a very high chance it's good
-    if let Some(entry) = table.get(applied.get_hash()) {
-        Stat::TableEvalHits.inc();
-        value += 1024 * entry.depth_of_search as i32 * entry.depth_of_score as i32;
-    }
+    value
+}
 
-    // Checking is often a good idea
-    if applied.checkers().0 != 0 {
-        value += 50;
-    }
+/// Most valuable victim, least valuable attacker.
+pub(super) fn eval_capture(board: &Board, cmove: ChessMove) -> i32 {
+    let moving_piece = board.piece_on(cmove.get_source()).unwrap();
+    let captured = board.piece_on(cmove.get_dest()).map_or(0, piece_value);
+    8 * captured - piece_value(moving_piece) / 10
+}
 
-    value
+/// If `cmove` captures a piece at least as valuable as the one capturing.
+pub(super) fn is_good_capture(board: &Board, cmove: ChessMove) -> bool {
+    let moving_piece = board.piece_on(cmove.get_source()).unwrap();
+    match board.piece_on(cmove.get_dest()) {
+        Some(captured_piece) => piece_value(captured_piece) >= piece_value(moving_piece),
+        None => false,
+    }
 }
 
 fn piece_value(piece: Piece) -> i32 {
@@ -157,27 +404,146 @@ fn get_king_adjacent_squares(pos: Square) -> BitBoard {
         | BitBoard::from_square(pos.right().unwrap_or(s))
         | BitBoard::from_square(pos.up().unwrap_or(s))
         | BitBoard::from_square(pos.down().unwrap_or(s))
-        | BitBoard::from_square(pos.left().map(|s| s.up()).flatten().unwrap_or(s))
-        | BitBoard::from_square(pos.right().map(|s| s.down()).flatten().unwrap_or(s))
-        | BitBoard::from_square(pos.up().map(|s| s.right()).flatten().unwrap_or(s))
-        | BitBoard::from_square(pos.down().map(|s| s.left()).flatten().unwrap_or(s))
+        | BitBoard::from_square(pos.left().and_then(|s| s.up()).unwrap_or(s))
+        | BitBoard::from_square(pos.right().and_then(|s| s.down()).unwrap_or(s))
+        | BitBoard::from_square(pos.up().and_then(|s| s.right()).unwrap_or(s))
+        | BitBoard::from_square(pos.down().and_then(|s| s.left()).unwrap_or(s))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::eval_board;
+    use super::{
+        eval_board, eval_mobility, eval_outposts, eval_rooks, eval_trapped_bishops, game_phase,
+        is_rook_trapped, Tapered, FULL_PHASE, MOBILITY, OUTPOST, ROOK_OPEN_FILE,
+        ROOK_SEMI_OPEN_FILE, ROOK_SEVENTH, TRAPPED_BISHOP, TRAPPED_ROOK,
+    };
     use crate::ai::evaluation::eval_static;
     use crate::ai::nnue;
-    use chess::Board;
+    use crate::ai::settings::Evaluator;
+    use crate::ai::table::TransTable;
+    use chess::{Board, Color, Square};
     use std::str::FromStr;
     use test::Bencher;
 
+    /// `fen` with the colours swapped and the board flipped, so the same side is better.
+    fn mirror(fen: &str) -> String {
+        let mut fields = fen.split(' ');
+        let ranks = fields.next().unwrap().split('/').rev().collect::<Vec<_>>();
+        let swap = |s: &str| {
+            s.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect::<String>()
+        };
+        let side = if fields.next() == Some("w") { "b" } else { "w" };
+        let castling = swap(fields.next().unwrap());
+        format!("{} {} {} - 0 1", swap(&ranks.join("/")), side, castling)
+    }
+
+    #[test]
+    fn test_symmetric() {
+        assert_eq!(eval_static(&Board::default(), None), 0);
+        for fen in [
+            "r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8",
+            "8/5pk1/6p1/3R4/8/6P1/5PK1/2r5 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            let mirrored = Board::from_str(&mirror(fen)).unwrap();
+            assert_eq!(
+                eval_static(&board, None),
+                eval_static(&mirrored, None),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_tapered() {
+        assert_eq!(game_phase(&Board::default()), FULL_PHASE);
+        let endgame = Board::from_str("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1").unwrap();
+        assert_eq!(game_phase(&endgame), 0);
+        assert_eq!(Tapered::new(100, 20).blend(FULL_PHASE), 100);
+        assert_eq!(Tapered::new(100, 20).blend(0), 20);
+        assert_eq!(Tapered::new(100, 20).blend(FULL_PHASE / 2), 60);
+
+        // A queen up is winning for either side to move
+        let board = Board::from_str("3qk3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(eval_static(&board, None) < -800);
+        let board = Board::from_str("3qk3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert!(eval_static(&board, None) > 800);
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    #[test]
+    fn test_mobility() {
+        // A centralised knight has all eight squares, one in the corner two
+        let centre = eval_mobility(&board("4k3/8/8/8/4N3/8/8/4K3 w - - 0 1"), Color::White);
+        let corner = eval_mobility(&board("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"), Color::White);
+        assert_eq!(centre - corner, MOBILITY[1] * 6);
+        // f5 is covered by the g6 pawn
+        let covered = eval_mobility(&board("4k3/8/6p1/8/3N4/8/8/4K3 w - - 0 1"), Color::White);
+        let free = eval_mobility(&board("4k3/6p1/8/8/3N4/8/8/4K3 w - - 0 1"), Color::White);
+        assert_eq!(free - covered, MOBILITY[1]);
+
+        // Shut in by its own king, which has lost the right to castle
+        let trapped = board("4k3/8/8/8/8/8/6PP/5K1R w - - 0 1");
+        let castled = board("4k3/8/8/8/8/8/6PP/5RK1 w - - 0 1");
+        assert!(is_rook_trapped(&trapped, Color::White, Square::H1));
+        assert!(!is_rook_trapped(&castled, Color::White, Square::F1));
+        let mobility = eval_mobility(&trapped, Color::White);
+        assert!(mobility.mg < (MOBILITY[3] * -5 + TRAPPED_ROOK).mg);
+    }
+
+    #[test]
+    fn test_piece_activity() {
+        // Defended by the e4 pawn, and no black pawn can drive it away
+        let outpost = board("4k3/p6p/8/3N4/4P3/8/8/4K3 w - - 0 1");
+        assert_eq!(eval_outposts(&outpost, Color::White), OUTPOST[0]);
+        let attackable = board("4k3/p1p4p/8/3N4/4P3/8/8/4K3 w - - 0 1");
+        assert_eq!(eval_outposts(&attackable, Color::White), Tapered::default());
+
+        let open = board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(eval_rooks(&open, Color::White), ROOK_OPEN_FILE);
+        let semi_open = board("p3k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(eval_rooks(&semi_open, Color::White), ROOK_SEMI_OPEN_FILE);
+        let seventh = board("4k3/R4ppp/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            eval_rooks(&seventh, Color::White),
+            ROOK_OPEN_FILE + ROOK_SEVENTH
+        );
+
+        let trapped = board("4k3/B6p/1p6/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(eval_trapped_bishops(&trapped, Color::White), TRAPPED_BISHOP);
+        let trapped = board("4k3/8/8/8/8/6P1/7b/4K3 b - - 0 1");
+        assert_eq!(eval_trapped_bishops(&trapped, Color::Black), TRAPPED_BISHOP);
+    }
+
+    #[test]
+    fn test_fallback() {
+        // Without a network, the classical evaluation is used either way
+        if !nnue::is_ready() {
+            let board = Board::from_str("3qk3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+            let table = TransTable::new();
+            assert_eq!(
+                eval_board(&board, &table, Evaluator::Nnue),
+                eval_board(&board, &table, Evaluator::Classical)
+            );
+        }
+    }
+
     #[bench]
     fn bench_static_eval(b: &mut Bencher) {
         let board =
             Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
                 .unwrap();
-        b.iter(|| eval_static(&board));
+        b.iter(|| eval_static(&board, None));
     }
 
     #[bench]
@@ -185,7 +551,8 @@ mod tests {
         let board =
             Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
                 .unwrap();
-        nnue::init();
-        b.iter(|| eval_board(&board));
+        assert!(nnue::init());
+        let table = TransTable::new();
+        b.iter(|| eval_board(&board, &table, Evaluator::Nnue));
     }
 }
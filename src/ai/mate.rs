@@ -0,0 +1,287 @@
+use chess::{Board, ChessMove, Color, MoveGen, EMPTY};
+use std::collections::HashMap;
+
+/// Proof and disproof numbers at least this large mean proven or disproven.
+const INFINITY: u32 = u32::MAX / 4;
+/// Longer mates are not looked for, so their plies fit in a `u16`
+pub(super) const MAX_MOVES: u16 = u16::MAX / 2;
+
+/// Proves or disproves "the side to move mates in at most N moves" with depth-first
+/// proof-number search (df-pn). Unlike alpha-beta, it needs no evaluation and is drawn
+/// towards the lines where the defender has the fewest replies, which is where mates are.
+///
+/// Numbers are stored for the side to move of each node ("phi" and "delta"):
+/// `phi` is the proof number of the side to move reaching its goal,
+/// `delta` its disproof number. The attacker's goal is to mate within the remaining plies,
+/// the defender's goal is to avoid that.
+pub struct MateSolver {
+    attacker: Color,
+    /// Keyed by position and remaining plies, which also keeps cycles out of the search
+    table: HashMap<(u64, u16), (u32, u32)>,
+    nodes: u64,
+}
+
+impl MateSolver {
+    pub fn new(board: &Board) -> Self {
+        Self {
+            attacker: board.side_to_move(),
+            table: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// Nodes expanded so far.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// The shortest mate of at most `max_moves` moves, with the best defence.
+    pub fn solve(&mut self, board: &Board, max_moves: u16) -> Option<Vec<ChessMove>> {
+        let moves = self.mate_in(board, max_moves.min(MAX_MOVES))?;
+        Some(self.line(board, moves))
+    }
+
+    /// The fewest moves the attacker needs to mate from `board`, with the attacker to move.
+    fn mate_in(&mut self, board: &Board, max_moves: u16) -> Option<u16> {
+        (1..=max_moves).find(|&moves| self.proves(board, 2 * moves - 1))
+    }
+
+    /// If the attacker mates within `plies` from `board`.
+    fn proves(&mut self, board: &Board, plies: u16) -> bool {
+        let (phi, delta) = self.mid(board, plies, INFINITY, INFINITY);
+        // Numbers are for the side to move
+        if board.side_to_move() == self.attacker {
+            phi == 0
+        } else {
+            delta == 0
+        }
+    }
+
+    /// The mating line from `board`, where the attacker mates in exactly `moves` moves.
+    /// The defender always picks the reply that delays the mate the longest.
+    fn line(&mut self, board: &Board, moves: u16) -> Vec<ChessMove> {
+        let plies = 2 * moves - 1;
+        let mov = MoveGen::new_legal(board)
+            .find(|m| self.proves(&board.make_move_new(*m), plies - 1))
+            .unwrap();
+        let after = board.make_move_new(mov);
+        let mut line = vec![mov];
+        if moves == 1 {
+            return line;
+        }
+
+        let (reply, remaining) = MoveGen::new_legal(&after)
+            .map(|reply| {
+                let remaining = self
+                    .mate_in(&after.make_move_new(reply), moves - 1)
+                    .unwrap();
+                (reply, remaining)
+            })
+            .max_by_key(|(_, remaining)| *remaining)
+            .unwrap();
+        line.push(reply);
+        line.extend(self.line(&after.make_move_new(reply), remaining));
+        line
+    }
+
+    /// Search `board` until its numbers reach the thresholds, and return them.
+    fn mid(&mut self, board: &Board, plies: u16, th_phi: u32, th_delta: u32) -> (u32, u32) {
+        self.nodes += 1;
+        if let Some(numbers) = self.terminal(board, plies) {
+            self.table.insert((board.get_hash(), plies), numbers);
+            return numbers;
+        }
+
+        let children = MoveGen::new_legal(board)
+            .map(|m| board.make_move_new(m))
+            .collect::<Vec<_>>();
+        loop {
+            // The side to move needs one child reaching its goal, so it is disproven
+            // only if all children reach theirs
+            let mut phi = INFINITY;
+            let mut delta: u32 = 0;
+            let mut second_phi = INFINITY;
+            let mut best = 0;
+            for (i, child) in children.iter().enumerate() {
+                let (child_phi, child_delta) = self.lookup(child, plies - 1);
+                delta = delta.saturating_add(child_phi).min(INFINITY);
+                if child_delta < phi {
+                    second_phi = phi;
+                    phi = child_delta;
+                    best = i;
+                } else if child_delta < second_phi {
+                    second_phi = child_delta;
+                }
+            }
+
+            if phi >= th_phi || delta >= th_delta {
+                self.table.insert((board.get_hash(), plies), (phi, delta));
+                return (phi, delta);
+            }
+
+            let (child_phi, child_delta) = self.lookup(&children[best], plies - 1);
+            let child_th_phi = (th_delta - delta).saturating_add(child_phi).min(INFINITY);
+            let child_th_delta = th_phi.min(second_phi.saturating_add(1));
+            debug_assert!(child_th_delta > child_delta);
+            self.mid(&children[best], plies - 1, child_th_phi, child_th_delta);
+        }
+    }
+
+    fn lookup(&self, board: &Board, plies: u16) -> (u32, u32) {
+        self.table
+            .get(&(board.get_hash(), plies))
+            .copied()
+            .unwrap_or((1, 1))
+    }
+
+    /// Numbers of a node that is decided without looking at its moves.
+    fn terminal(&self, board: &Board, plies: u16) -> Option<(u32, u32)> {
+        let lost = (INFINITY, 0);
+        let won = (0, INFINITY);
+        let attacker_fails = if board.side_to_move() == self.attacker {
+            lost
+        } else {
+            won
+        };
+        if MoveGen::new_legal(board).len() == 0 {
+            if board.checkers() != &EMPTY {
+                Some(lost)
+            } else {
+                Some(attacker_fails)
+            }
+        } else if plies == 0 {
+            Some(attacker_fails)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MateSolver;
+    use chess::{Board, BoardStatus, ChessMove, MoveGen, Square};
+    use std::str::FromStr;
+
+    /// If the side to move mates within `plies`, by trying everything.
+    fn brute_force(board: &Board, plies: u16, attacking: bool) -> bool {
+        match board.status() {
+            BoardStatus::Checkmate => return !attacking,
+            BoardStatus::Stalemate => return false,
+            BoardStatus::Ongoing if plies == 0 => return false,
+            BoardStatus::Ongoing => (),
+        }
+        let mut children = MoveGen::new_legal(board).map(|m| board.make_move_new(m));
+        if attacking {
+            children.any(|child| brute_force(&child, plies - 1, false))
+        } else {
+            children.all(|child| brute_force(&child, plies - 1, true))
+        }
+    }
+
+    fn assert_mates(board: &Board, line: &[ChessMove]) {
+        let mut board = *board;
+        for mov in line {
+            assert!(board.legal(*mov), "{} on {}", mov, board);
+            board = board.make_move_new(*mov);
+        }
+        assert_eq!(board.status(), BoardStatus::Checkmate);
+    }
+
+    #[test]
+    fn test_known_problems() {
+        let problems = [
+            // Back rank mate
+            (
+                "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+                1,
+                Square::D1,
+                Square::D8,
+            ),
+            // Sacrifice to open the a-file for the pawn
+            (
+                "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1",
+                2,
+                Square::A1,
+                Square::A6,
+            ),
+            // Black mates as well
+            (
+                "3r2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1",
+                1,
+                Square::D8,
+                Square::D1,
+            ),
+            // Philidor's legacy: smothered mate after a double check and a queen sacrifice
+            (
+                "r5k1/5Npp/8/8/8/1Q6/6PP/6K1 w - - 0 1",
+                3,
+                Square::F7,
+                Square::H6,
+            ),
+            // The same, with the knight check first
+            (
+                "r6k/6pp/8/6N1/8/1Q6/6PP/6K1 w - - 0 1",
+                4,
+                Square::G5,
+                Square::F7,
+            ),
+            // The same, with the queen driving the king into the corner first
+            (
+                "5rk1/6pp/8/6N1/8/8/6PP/2Q3K1 w - - 0 1",
+                5,
+                Square::C1,
+                Square::C4,
+            ),
+        ];
+        for (fen, moves, from, to) in problems.iter() {
+            let board = Board::from_str(fen).unwrap();
+            let mut solver = MateSolver::new(&board);
+            let line = solver.solve(&board, 5).unwrap();
+            assert_eq!(line.len(), 2 * *moves as usize - 1, "{}", fen);
+            assert_eq!(line[0], ChessMove::new(*from, *to, None), "{}", fen);
+            assert_mates(&board, &line);
+        }
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let positions = [
+            "7k/8/5K2/8/8/8/8/6Q1 w - - 0 1",
+            "8/8/8/8/8/3k4/8/K5Q1 w - - 0 1",
+            "k7/8/2K5/8/8/8/8/1R6 w - - 0 1",
+            "4k3/8/4K3/8/8/8/8/R7 w - - 0 1",
+            "8/8/8/3k4/8/8/8/3KQ3 w - - 0 1",
+            "k7/8/8/1K6/8/8/8/7R w - - 0 1",
+            "2k5/8/2K5/8/8/8/8/7R w - - 0 1",
+            "k7/8/8/8/2K5/8/8/7R w - - 0 1",
+            "1k6/8/3K4/8/8/8/8/7R w - - 0 1",
+        ];
+        for fen in positions.iter() {
+            let board = Board::from_str(fen).unwrap();
+            let mut solver = MateSolver::new(&board);
+            let expected = (1..=3).find(|&n| brute_force(&board, 2 * n - 1, true));
+            let line = solver.solve(&board, 3);
+            assert_eq!(
+                line.as_ref().map(|l| (l.len() as u16).div_ceil(2)),
+                expected,
+                "{}",
+                fen
+            );
+            if let Some(line) = line {
+                assert_mates(&board, &line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_mate() {
+        // Stalemate traps and lone kings are not mates
+        let board = Board::from_str("k7/8/1Q6/8/8/8/8/7K b - - 0 1").unwrap();
+        assert_eq!(MateSolver::new(&board).solve(&board, 2), None);
+        // Without overflowing the plies
+        assert_eq!(MateSolver::new(&board).solve(&board, u16::MAX), None);
+        let board = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(MateSolver::new(&board).solve(&board, 3), None);
+    }
+}
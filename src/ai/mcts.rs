@@ -0,0 +1,338 @@
+use crate::ai::draw::Line;
+use crate::ai::evaluation;
+use crate::ai::searcher::{Info, Limits, Report, Searcher, SharedState};
+use crate::ai::settings::Settings;
+use crate::ai::table::TransTable;
+use chess::{Board, ChessMove, Color, MoveGen, EMPTY};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How much the search prefers moves with a high prior and few visits
+const EXPLORATION: f32 = 1.5;
+/// Centipawns that make a side about 10 times as likely to win as to lose
+const WIN_PROBABILITY_SCALE: f32 = 400.0;
+/// Softmax temperature of the move evaluation for priors, in centipawns
+const PRIOR_TEMPERATURE: f32 = 100.0;
+/// Move evaluations are clamped to this, so table bonuses don't hide all other moves
+const PRIOR_LIMIT: i32 = 1000;
+/// Value of a move that was never visited
+const FIRST_PLAY_VALUE: f32 = 0.4;
+/// Values are summed as fixed point numbers in atomics
+const VALUE_SCALE: f32 = 1_000_000.0;
+/// Time between progress updates
+const INFO_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Monte Carlo tree search with PUCT selection: Positions are valued by the NNUE evaluation
+/// converted to a win probability, and moves are tried more often the better their
+/// `eval_move` score. Searches on all threads of the rayon pool, which avoid searching
+/// the same line at the same time through virtual losses.
+#[derive(Default)]
+pub struct Mcts;
+
+impl Searcher for Mcts {
+    fn name(&self) -> &'static str {
+        "mcts"
+    }
+
+    fn search(
+        &mut self,
+        state: &SharedState,
+        board: Board,
+        history: &[u64],
+        settings: Settings,
+        limits: Limits,
+        info: &mut dyn FnMut(&Info),
+    ) -> Report {
+        let search = Search {
+            table: &state.table,
+            settings,
+            engine: board.side_to_move(),
+            playouts: AtomicU64::new(0),
+            max_ply: AtomicUsize::new(0),
+            limits: &limits,
+        };
+        let root = Node::new(ChessMove::default(), 1.0);
+        let root_line = Line::root(&board, history);
+        let start = Instant::now();
+        let done = || match limits.nodes {
+            Some(nodes) => search.playouts.load(Ordering::Relaxed) >= nodes,
+            None => start.elapsed().as_secs_f32() >= limits.time,
+        };
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                (0..rayon::current_num_threads())
+                    .into_par_iter()
+                    .for_each(|_| {
+                        while !done() {
+                            search.playout(&root, &board, &root_line);
+                            search.playouts.fetch_add(1, Ordering::Relaxed);
+                        }
+                    })
+            });
+
+            let mut best = None;
+            while !done() {
+                match root.most_visited() {
+                    Some(child) if best != Some(child.mov) => {
+                        best = Some(child.mov);
+                        info(&Info::BestMove {
+                            depth: search.max_ply.load(Ordering::Relaxed) as i16,
+                            mov: child.mov,
+                            score: to_centipawns(child.value()),
+                        });
+                    }
+                    _ => (),
+                }
+                thread::sleep(INFO_INTERVAL);
+            }
+        });
+
+        let mut report = Report::unsearched(ChessMove::default());
+        if let Some(best) = root.most_visited() {
+            report.best = best.mov;
+            report.score = to_centipawns(best.value());
+        }
+        report.depth = search.max_ply.load(Ordering::Relaxed) as i16;
+        report.nodes = search.playouts.load(Ordering::Relaxed);
+        report.pv = root.principal_variation();
+        report.visits = root
+            .children
+            .get()
+            .map(|children| {
+                children
+                    .iter()
+                    .map(|child| (child.mov, child.visits.load(Ordering::Relaxed)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        report
+            .visits
+            .sort_unstable_by_key(|(_, visits)| u32::MAX - visits);
+        log::info!(
+            "{} playouts, {} plies deep, score {}, visits {}",
+            report.nodes,
+            report.depth,
+            report.score,
+            report
+                .visits
+                .iter()
+                .take(5)
+                .map(|(mov, visits)| format!("{} {}", mov, visits))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        report
+    }
+}
+
+struct Search<'a> {
+    table: &'a TransTable,
+    settings: Settings,
+    /// The side the engine is playing
+    engine: Color,
+    playouts: AtomicU64,
+    /// Deepest ply reached by any playout
+    max_ply: AtomicUsize,
+    limits: &'a Limits,
+}
+
+impl Search<'_> {
+    /// Walk down the tree from `node`, evaluate a new leaf and update the nodes on the way.
+    /// Returns the value of the position for the side to move.
+    fn playout(&self, node: &Node, board: &Board, line: &Line) -> f32 {
+        if line.ply() > 0 && line.is_draw(board) {
+            return self.draw_value(board);
+        }
+        if line.ply() > 0 && node.visits.load(Ordering::Relaxed) == 0 {
+            self.max_ply.fetch_max(line.ply(), Ordering::Relaxed);
+            return self.evaluate(board);
+        }
+
+        let children = node
+            .children
+            .get_or_init(|| self.expand(board, line.ply() == 0));
+        let child = match node.select(children) {
+            Some(child) => child,
+            None if board.checkers() != &EMPTY => return 0.0,
+            None => return self.draw_value(board),
+        };
+
+        child.virtual_loss.fetch_add(1, Ordering::Relaxed);
+        let after = board.make_move_new(child.mov);
+        let child_line = line.child(board, child.mov, &after);
+        let value = 1.0 - self.playout(child, &after, &child_line);
+        child.add(value);
+        child.virtual_loss.fetch_sub(1, Ordering::Relaxed);
+        if line.ply() == 0 {
+            node.add(value);
+        }
+        value
+    }
+
+    fn evaluate(&self, board: &Board) -> f32 {
+        match MoveGen::new_legal(board).len() {
+            0 if board.checkers() != &EMPTY => 0.0,
+            0 => self.draw_value(board),
+            _ => win_probability(evaluation::eval_board(
+                board,
+                self.table,
+                self.settings.evaluator,
+            )),
+        }
+    }
+
+    /// All legal moves of `board`, with priors from their evaluation.
+    /// At the `root`, only the moves allowed by the limits.
+    fn expand(&self, board: &Board, root: bool) -> Box<[Node]> {
+        let scores = MoveGen::new_legal(board)
+            .filter(|m| !root || self.limits.allows(*m))
+            .map(|m| {
+                let score = evaluation::eval_move(board, self.table, m);
+                (m, score.clamp(-PRIOR_LIMIT, PRIOR_LIMIT) as f32)
+            })
+            .collect::<Vec<_>>();
+        let max = scores.iter().map(|(_, s)| *s).fold(f32::MIN, f32::max);
+        let weights = scores
+            .iter()
+            .map(|(m, s)| (*m, ((s - max) / PRIOR_TEMPERATURE).exp()))
+            .collect::<Vec<_>>();
+        let sum = weights.iter().map(|(_, w)| w).sum::<f32>();
+        weights
+            .into_iter()
+            .map(|(m, w)| Node::new(m, w / sum))
+            .collect()
+    }
+
+    fn draw_value(&self, board: &Board) -> f32 {
+        win_probability(self.settings.contempt.draw_score(board, self.engine))
+    }
+}
+
+struct Node {
+    /// The move leading to this node
+    mov: ChessMove,
+    prior: f32,
+    visits: AtomicU32,
+    /// Sum of the values for the side that played `mov`, times `VALUE_SCALE`
+    value_sum: AtomicI64,
+    /// Threads currently searching below this node
+    virtual_loss: AtomicU32,
+    children: OnceLock<Box<[Node]>>,
+}
+
+impl Node {
+    fn new(mov: ChessMove, prior: f32) -> Self {
+        Self {
+            mov,
+            prior,
+            visits: AtomicU32::new(0),
+            value_sum: AtomicI64::new(0),
+            virtual_loss: AtomicU32::new(0),
+            children: OnceLock::new(),
+        }
+    }
+
+    fn add(&self, value: f32) {
+        self.visits.fetch_add(1, Ordering::Relaxed);
+        self.value_sum
+            .fetch_add((value * VALUE_SCALE) as i64, Ordering::Relaxed);
+    }
+
+    /// Average value for the side that played `mov`.
+    fn value(&self) -> f32 {
+        match self.visits.load(Ordering::Relaxed) {
+            0 => FIRST_PLAY_VALUE,
+            visits => self.value_sum.load(Ordering::Relaxed) as f32 / VALUE_SCALE / visits as f32,
+        }
+    }
+
+    /// The child with the best PUCT score; virtual losses count as visits that were lost.
+    fn select<'a>(&self, children: &'a [Node]) -> Option<&'a Node> {
+        let parent_visits = self.visits.load(Ordering::Relaxed) as f32 + 1.0;
+        let exploration = EXPLORATION * parent_visits.sqrt();
+        let puct = |child: &Node| {
+            let visits = child.visits.load(Ordering::Relaxed);
+            let virtual_loss = child.virtual_loss.load(Ordering::Relaxed);
+            let value = match visits + virtual_loss {
+                0 => FIRST_PLAY_VALUE,
+                n => child.value() * visits as f32 / n as f32,
+            };
+            value + exploration * child.prior / (1 + visits + virtual_loss) as f32
+        };
+        children.iter().max_by(|a, b| puct(a).total_cmp(&puct(b)))
+    }
+
+    fn most_visited(&self) -> Option<&Node> {
+        self.children
+            .get()?
+            .iter()
+            .max_by_key(|child| child.visits.load(Ordering::Relaxed))
+            .filter(|child| child.visits.load(Ordering::Relaxed) > 0)
+    }
+
+    /// The most visited line of play from this node.
+    fn principal_variation(&self) -> Vec<ChessMove> {
+        let mut pv = Vec::new();
+        let mut node = self;
+        while let Some(child) = node.most_visited() {
+            pv.push(child.mov);
+            node = child;
+        }
+        pv
+    }
+}
+
+fn win_probability(centipawns: i32) -> f32 {
+    1.0 / (1.0 + 10f32.powf(-centipawns as f32 / WIN_PROBABILITY_SCALE))
+}
+
+fn to_centipawns(probability: f32) -> i32 {
+    let probability = probability.clamp(0.001, 0.999);
+    (-WIN_PROBABILITY_SCALE * (1.0 / probability - 1.0).log10()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_centipawns, win_probability, Mcts};
+    use crate::ai::ordering::Heuristics;
+    use crate::ai::searcher::{Limits, Searcher, SharedState};
+    use crate::ai::settings::Settings;
+    use crate::ai::table::TransTable;
+    use chess::{Board, ChessMove, Square};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_win_probability() {
+        assert_eq!(win_probability(0), 0.5);
+        assert!((win_probability(400) - 10.0 / 11.0).abs() < 1e-6);
+        assert!((win_probability(-250) + win_probability(250) - 1.0).abs() < 1e-6);
+        assert!((to_centipawns(win_probability(123)) - 123).abs() <= 1);
+    }
+
+    #[test]
+    fn test_finds_mate() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let state = SharedState {
+            table: Arc::new(TransTable::new()),
+            heuristics: Arc::new(Heuristics::new()),
+            tree: None,
+        };
+        let limits = Limits {
+            time: 0.0,
+            nodes: Some(3000),
+            search_moves: Vec::new(),
+        };
+        let report = Mcts.search(&state, board, &[], Settings::default(), limits, &mut |_| ());
+        let mate = ChessMove::new(Square::D1, Square::D8, None);
+        assert_eq!(report.best, mate);
+        assert_eq!(report.pv[0], mate);
+        let visits = report.visits.iter().map(|(_, v)| v).sum::<u32>();
+        assert!(visits >= 3000);
+        assert!(report.score > 1000);
+    }
+}
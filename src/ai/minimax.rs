@@ -1,208 +1,991 @@
 use crate::ai;
+use crate::ai::draw::Line;
+use crate::ai::ordering::{Heuristics, MovePicker};
+use crate::ai::searcher::{Info, Limits, Report, RootMove, Searcher, SharedState};
+use crate::ai::settings::Settings;
 use crate::ai::statistics::Stat;
-use crate::ai::table::{Entry, TransTable};
+use crate::ai::table::{Bound, Entry, TransTable};
+use crate::ai::time::TimeManager;
+use crate::ai::tree::{Cutoff, NodeKind, SearchTree};
 use crate::ai::{evaluation, RatedMove};
-use chess::{Board, ChessMove, EMPTY};
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, EMPTY};
 use rayon::iter::Either;
 use rayon::prelude::*;
-use std::sync::atomic::{AtomicBool, Ordering};
+use rayon::ThreadPoolBuilder;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 const INF: i32 = 999999999;
-const WIN: i32 = 999999;
+pub(super) const WIN: i32 = 999999;
+/// The first depth of iterative deepening
+const START_DEPTH: i16 = 2;
+/// The last depth of iterative deepening; depths are stored in 8 bits in the table
+const MAX_DEPTH: i16 = 127;
+/// Minimum remaining depth for internal iterative deepening
+const IID_DEPTH: i16 = 4;
+/// How much shallower the internal iterative deepening search is
+const IID_REDUCTION: i16 = 2;
 
-pub fn calculate_move(board: Board, time: f32) -> ChessMove {
-    let mut cmove = ChessMove::default();
+thread_local! {
+    /// Nodes searched on this thread, to count the nodes of each root move
+    static THREAD_NODES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Nodes searched on this thread so far. The subtree of a root move is searched
+/// on a single thread, so the difference around it is the nodes of the move.
+fn thread_nodes() -> u64 {
+    THREAD_NODES.with(Cell::get)
+}
+
+/// State shared by every node of a single search.
+struct Search<'a> {
+    table: &'a TransTable,
+    heuristics: &'a Heuristics,
+    run: &'a AtomicBool,
+    /// Positions played before the root since the last irreversible move
+    history: &'a [u64],
+    settings: Settings,
+    /// The side the engine is playing
+    engine: Color,
+    /// Clamp all scores to the search window, to test fail-soft against fail-hard results
+    fail_hard: bool,
+    /// Nodes searched so far
+    nodes: AtomicU64,
+    node_limit: Option<u64>,
+    /// Records the search tree, for debugging
+    tree: Option<&'a SearchTree>,
+    /// Only search these root moves; all of them if empty
+    search_moves: &'a [ChessMove],
+}
+
+impl Search<'_> {
+    fn stopped(&self) -> bool {
+        !self.run.load(Ordering::Relaxed)
+            || self
+                .node_limit
+                .is_some_and(|limit| self.nodes.load(Ordering::Relaxed) >= limit)
+    }
+
+    fn count_node(&self) {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        THREAD_NODES.with(|nodes| nodes.set(nodes.get() + 1));
+    }
+
+    /// The result of a root move, with the line of play from the table.
+    fn root_move(
+        &self,
+        board: Board,
+        mov: ChessMove,
+        depth: i16,
+        score: i32,
+        bound: Bound,
+        nodes: u64,
+    ) -> Info {
+        Info::RootMove(RootMove {
+            mov,
+            score,
+            bound,
+            nodes,
+            depth,
+            pv: principal_variation(self.table, board, mov, depth as usize),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn traced(
+        &self,
+        kind: NodeKind,
+        mov: Option<ChessMove>,
+        depth: i16,
+        alpha: i32,
+        beta: i32,
+        search: impl FnOnce() -> i32,
+    ) -> i32 {
+        match self.tree {
+            Some(tree) => tree.record(kind, mov, depth, alpha, beta, search),
+            None => search(),
+        }
+    }
+
+    /// Note why the current node returned early, if the tree is recorded.
+    fn cutoff(&self, cutoff: Cutoff) {
+        if let Some(tree) = self.tree {
+            tree.cutoff(cutoff);
+        }
+    }
+
+    fn table_hit(&self) {
+        if let Some(tree) = self.tree {
+            tree.table_hit();
+        }
+    }
+
+    /// The search is fail-soft: Scores outside of the window are returned as they are,
+    /// which gives the table tighter bounds to work with.
+    fn result(&self, score: i32, alpha: i32, beta: i32) -> i32 {
+        if self.fail_hard {
+            score.clamp(alpha, beta)
+        } else {
+            score
+        }
+    }
+
+    fn draw_score(&self, board: &Board) -> i32 {
+        self.settings.contempt.draw_score(board, self.engine)
+    }
+}
+
+/// Principal variation search with iterative deepening,
+/// searching the root moves on all threads of the rayon pool.
+#[derive(Default)]
+pub struct Pvs;
+
+impl Searcher for Pvs {
+    fn name(&self) -> &'static str {
+        "pvs"
+    }
+
+    fn search(
+        &mut self,
+        state: &SharedState,
+        board: Board,
+        history: &[u64],
+        settings: Settings,
+        limits: Limits,
+        info: &mut dyn FnMut(&Info),
+    ) -> Report {
+        iterative_deepening(calc_depth, state, board, history, settings, limits, info)
+    }
+}
+
+/// MTD(f): Finds the value of the root with a series of zero window searches,
+/// each one narrowing the bounds stored in the table, starting from the previous depth's value.
+#[derive(Default)]
+pub struct Mtdf;
+
+impl Searcher for Mtdf {
+    fn name(&self) -> &'static str {
+        "mtdf"
+    }
+
+    fn search(
+        &mut self,
+        state: &SharedState,
+        board: Board,
+        history: &[u64],
+        settings: Settings,
+        limits: Limits,
+        info: &mut dyn FnMut(&Info),
+    ) -> Report {
+        iterative_deepening(mtdf_depth, state, board, history, settings, limits, info)
+    }
+}
+
+/// Searches the root moves at one depth, leaving the best move first.
+/// Progress during the depth, like moves proven better than the previous best move
+/// and the results of each root move, may be passed to the callback.
+type RootDriver = fn(&Search, Board, i16, &mut Vec<RatedMove>, &(dyn Fn(Info) + Sync));
+
+/// Search deeper and deeper with `driver` until the limits are reached.
+fn iterative_deepening(
+    driver: RootDriver,
+    state: &SharedState,
+    board: Board,
+    history: &[u64],
+    settings: Settings,
+    limits: Limits,
+    info: &mut dyn FnMut(&Info),
+) -> Report {
+    let report = match limits.nodes {
+        Some(nodes) => search_nodes(
+            &state.table,
+            &state.heuristics,
+            board,
+            history,
+            settings,
+            nodes,
+            state.tree.as_deref(),
+            &limits.search_moves,
+            driver,
+            info,
+        ),
+        None => search_time(driver, state, board, history, settings, &limits, info),
+    };
+    log::info!(
+        "Searched {} nodes to depth {}, score {}, PV {}",
+        report.nodes,
+        report.depth,
+        report.score,
+        report
+            .pv
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    report
+}
+
+/// Search on all threads until the time manager decides to stop.
+fn search_time(
+    driver: RootDriver,
+    state: &SharedState,
+    board: Board,
+    history: &[u64],
+    settings: Settings,
+    limits: &Limits,
+    info: &mut dyn FnMut(&Info),
+) -> Report {
+    let mut report = Report::unsearched(ChessMove::default());
     let (move_tx, move_rx) = mpsc::channel();
-    let run = AtomicBool::new(true);
-    let start_time = Instant::now();
+    let run = Arc::new(AtomicBool::new(true));
+    let moves = MoveGen::new_legal(&board)
+        .filter(|m| limits.allows(*m))
+        .count();
+    let mut time = TimeManager::new(limits.time, moves);
 
-    thread::spawn(move || run_until_stopped(board, move_tx, &run));
+    let search_run = run.clone();
+    let search_state = state.clone();
+    let history = history.to_vec();
+    let search_moves = limits.search_moves.clone();
+    let handle = thread::spawn(move || {
+        let search = Search {
+            table: &search_state.table,
+            heuristics: &search_state.heuristics,
+            run: &search_run,
+            history: &history,
+            settings,
+            engine: board.side_to_move(),
+            fail_hard: false,
+            nodes: AtomicU64::new(0),
+            node_limit: None,
+            tree: search_state.tree.as_deref(),
+            search_moves: &search_moves,
+        };
+        run_until_stopped(&search, board, move_tx, driver);
+        search.nodes.into_inner()
+    });
 
-    while start_time.elapsed().as_secs_f32() < time {
-        if let Ok(value) = move_rx.try_recv() {
-            cmove = value;
+    loop {
+        // The search ends by itself at the last depth, or when every root move is decided
+        let finished = handle.is_finished();
+        while let Ok(update) = move_rx.try_recv() {
+            match update {
+                Info::BestMove { mov, .. } => time.best_move_found(mov),
+                Info::Depth { best, score, .. } => time.depth_finished(best, score),
+                _ => (),
+            }
+            report.update(&update);
+            info(&update);
+        }
+        if finished || time.should_stop() {
+            break;
         }
         thread::sleep(Duration::from_millis(1));
     }
 
-    cmove
+    // Stop the search, so the table is free for the next move
+    run.store(false, Ordering::Relaxed);
+    report.nodes = handle.join().unwrap();
+    report.pv = principal_variation(&state.table, board, report.best, report.depth as usize);
+    report
+}
+
+/// Search on a single thread until `nodes` nodes are searched.
+/// Nothing depends on timing, so the same input always gives the same report.
+#[allow(clippy::too_many_arguments)]
+fn search_nodes(
+    table: &TransTable,
+    heuristics: &Heuristics,
+    board: Board,
+    history: &[u64],
+    settings: Settings,
+    nodes: u64,
+    tree: Option<&SearchTree>,
+    search_moves: &[ChessMove],
+    driver: RootDriver,
+    info: &mut dyn FnMut(&Info),
+) -> Report {
+    let run = AtomicBool::new(true);
+    let search = Search {
+        table,
+        heuristics,
+        run: &run,
+        history,
+        settings,
+        engine: board.side_to_move(),
+        fail_hard: false,
+        nodes: AtomicU64::new(0),
+        node_limit: Some(nodes),
+        tree,
+        search_moves,
+    };
+    let (move_tx, move_rx) = mpsc::channel();
+    let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    pool.install(|| run_until_stopped(&search, board, move_tx, driver));
+
+    let first =
+        MoveGen::new_legal(&board).find(|m| search_moves.is_empty() || search_moves.contains(m));
+    let mut report = Report::unsearched(first.unwrap_or_default());
+    for update in move_rx.try_iter() {
+        report.update(&update);
+        info(&update);
+    }
+    report.nodes = search.nodes.load(Ordering::Relaxed);
+    report.pv = principal_variation(table, board, report.best, report.depth as usize);
+    report
+}
+
+/// The expected line of play starting with `best`, following the best moves in the table.
+fn principal_variation(
+    table: &TransTable,
+    mut board: Board,
+    best: ChessMove,
+    max_len: usize,
+) -> Vec<ChessMove> {
+    let mut pv = vec![best];
+    board = board.make_move_new(best);
+    let mut seen = vec![board.get_hash()];
+    while pv.len() < max_len {
+        let mov = match table
+            .get(board.get_hash())
+            .and_then(|entry| entry.best_move)
+            .filter(|m| board.legal(*m))
+        {
+            Some(mov) => mov,
+            None => break,
+        };
+        pv.push(mov);
+        board = board.make_move_new(mov);
+        if seen.contains(&board.get_hash()) {
+            break;
+        }
+        seen.push(board.get_hash());
+    }
+    pv
 }
 
 #[cfg(test)]
 pub fn calculate_move_until_depth(board: Board, depth: i16) -> ChessMove {
+    search_until_depth(board, depth, Settings::default(), false)
+}
+
+#[cfg(test)]
+fn search_until_depth(board: Board, depth: i16, settings: Settings, fail_hard: bool) -> ChessMove {
+    with_test_search(&board, settings, fail_hard, |search| {
+        let mut moves = ai::sorted_moves(&board, search.table);
+        calc_depth(search, board, depth, &mut moves, &|_| ());
+        moves[0].0
+    })
+}
+
+#[cfg(test)]
+fn with_test_search<T>(
+    board: &Board,
+    settings: Settings,
+    fail_hard: bool,
+    f: impl FnOnce(&Search) -> T,
+) -> T {
     let table = TransTable::new();
-    let mut moves = ai::sorted_moves(&board, &table);
-    calc_depth(board, &table, depth, &mut moves);
-    moves[0].0
+    let heuristics = Heuristics::new();
+    let run = AtomicBool::new(true);
+    f(&Search {
+        table: &table,
+        heuristics: &heuristics,
+        run: &run,
+        history: &[],
+        settings,
+        engine: board.side_to_move(),
+        fail_hard,
+        nodes: AtomicU64::new(0),
+        node_limit: None,
+        tree: None,
+        search_moves: &[],
+    })
 }
 
-fn run_until_stopped(board: Board, move_tx: Sender<ChessMove>, run: &AtomicBool) {
+fn run_until_stopped(search: &Search, board: Board, move_tx: Sender<Info>, driver: RootDriver) {
     let start_time = Instant::now();
-    let mut depth = 2;
-    let table = TransTable::new();
-    let mut moves = ai::sorted_moves(&board, &table);
+    let mut depth = START_DEPTH;
+    search.table.new_search();
+    search.heuristics.new_search();
+    let mut moves = ai::sorted_moves(&board, search.table);
+    if !search.search_moves.is_empty() {
+        moves.retain(|(mov, _)| search.search_moves.contains(mov));
+    }
+    if moves.is_empty() {
+        return;
+    }
+    // Root moves to positions that are drawn already, which deeper searches cannot change
+    let root = Line::root(&board, search.history);
+    let draws = moves
+        .iter()
+        .map(|(mov, _)| *mov)
+        .filter(|mov| {
+            let after = board.make_move_new(*mov);
+            root.child(&board, *mov, &after).is_draw(&after)
+                || after.status() == BoardStatus::Stalemate
+        })
+        .collect::<Vec<_>>();
+
+    // Sender is not Sync, but the root moves are searched on several threads
+    let move_tx = Mutex::new(move_tx);
+    let publish = |info: Info| {
+        move_tx.lock().unwrap().send(info).ok();
+    };
 
-    while run.load(Ordering::Relaxed) {
-        calc_depth(board, &table, depth, &mut moves);
-        move_tx.send(moves[0].0).ok();
+    while !search.stopped() {
+        driver(search, board, depth, &mut moves, &publish);
+        if search.stopped() {
+            // Results of an interrupted depth are incomplete;
+            // any move proven better during it was already published
+            break;
+        }
+        let (best, score) = moves[0];
+        move_tx
+            .lock()
+            .unwrap()
+            .send(Info::Depth { depth, best, score })
+            .ok();
         log::info!(
             "Reached depth {} with {} moves in {}s",
             depth,
             moves.len(),
             start_time.elapsed().as_secs_f32()
         );
-        log::debug!("Best Move: {}", moves[0].0);
+        log::debug!("Best Move: {} ({})", best, score);
+        let decided = moves
+            .iter()
+            .all(|(mov, score)| score.abs() >= WIN || draws.contains(mov));
+        if depth >= MAX_DEPTH || decided {
+            break;
+        }
         depth += 1;
         Stat::next_depth();
     }
 }
 
-fn calc_depth(board: Board, table: &TransTable, depth: i16, moves: &mut Vec<RatedMove>) {
+/// Results of the root moves searched so far during one depth.
+#[derive(Default)]
+struct RootProgress {
+    /// If the previous best move has finished at this depth
+    pv_done: bool,
+    best: Option<RatedMove>,
+    published: Option<ChessMove>,
+}
+
+/// Search all root moves at `depth`, then sort them by their new scores.
+/// As soon as a move is proven better than the previous best move
+/// at this depth, it is passed to `publish`, like the exact score of each move.
+fn calc_depth(
+    search: &Search,
+    board: Board,
+    depth: i16,
+    moves: &mut Vec<RatedMove>,
+    publish: &(dyn Fn(Info) + Sync),
+) {
     if depth >= 4 {
         moves.truncate(usize::max(5, moves.len() / 2));
     }
-    moves.par_iter_mut().for_each(|(mov, score)| {
-        let time = Instant::now();
-        let clone = board.make_move_new(*mov);
-        *score = -minimax(&clone, table, depth - 1, depth, -INF, INF);
-        log::trace!(
-            "Spent {}s on move {} at depth {}",
-            time.elapsed().as_secs_f32(),
-            mov,
-            depth
-        );
-    });
+    let root = Line::root(&board, search.history);
+    let pv_move = moves[0].0;
+    let progress = Mutex::new(RootProgress::default());
+    moves
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(i, (mov, score))| {
+            let time = Instant::now();
+            publish(Info::CurrentMove {
+                depth,
+                mov: *mov,
+                number: i + 1,
+            });
+            let clone = board.make_move_new(*mov);
+            let line = root.child(&board, *mov, &clone);
+            let start = thread_nodes();
+            *score = -minimax(search, &clone, &line, depth - 1, depth, -INF, INF);
+            if search.stopped() {
+                return;
+            }
+            let nodes = thread_nodes() - start;
+            publish(search.root_move(board, *mov, depth, *score, Bound::Exact, nodes));
+
+            let mut progress = progress.lock().unwrap();
+            if progress.best.is_none_or(|(_, best)| *score > best) {
+                progress.best = Some((*mov, *score));
+            }
+            progress.pv_done |= *mov == pv_move;
+            match progress.best {
+                Some((best, best_score))
+                    if progress.pv_done && progress.published != Some(best) =>
+                {
+                    log::debug!(
+                        "New best move {} ({}) during depth {}",
+                        best,
+                        best_score,
+                        depth
+                    );
+                    progress.published = Some(best);
+                    publish(Info::BestMove {
+                        depth,
+                        mov: best,
+                        score: best_score,
+                    });
+                }
+                _ => (),
+            }
+            drop(progress);
+
+            log::trace!(
+                "Spent {}s on move {} at depth {}",
+                time.elapsed().as_secs_f32(),
+                mov,
+                depth
+            );
+        });
     moves.par_sort_unstable_by_key(|mov| -mov.1);
 }
 
+/// Find the value of the root at `depth` with zero window searches around a guess,
+/// moving the guess towards the value until the lower and upper bound meet.
+/// Only the best move gets an exact score, the others are reported with the bounds
+/// of their zero window searches; the root moves are searched one at a time.
+fn mtdf_depth(
+    search: &Search,
+    board: Board,
+    depth: i16,
+    moves: &mut Vec<RatedMove>,
+    publish: &(dyn Fn(Info) + Sync),
+) {
+    let root = Line::root(&board, search.history);
+    let mut guess = if depth == START_DEPTH {
+        // The scores are only move ordering estimates before the first depth
+        evaluation::eval_board(&board, search.table, search.settings.evaluator)
+    } else {
+        moves[0].1
+    };
+    let (mut lower, mut upper) = (-INF, INF);
+    let mut best = 0;
+    // Nodes of each root move over all zero window searches
+    let mut nodes = vec![0; moves.len()];
+    while lower < upper {
+        let beta = if guess == lower { guess + 1 } else { guess };
+        guess = -INF;
+        for (i, (mov, _)) in moves.iter().enumerate() {
+            publish(Info::CurrentMove {
+                depth,
+                mov: *mov,
+                number: i + 1,
+            });
+            let after = board.make_move_new(*mov);
+            let line = root.child(&board, *mov, &after);
+            let start = thread_nodes();
+            let score = -scout_search(search, &after, &line, depth - 1, depth, 1 - beta);
+            if search.stopped() {
+                return;
+            }
+            nodes[i] += thread_nodes() - start;
+            let bound = if score >= beta {
+                Bound::Lower
+            } else {
+                Bound::Upper
+            };
+            publish(search.root_move(board, *mov, depth, score, bound, nodes[i]));
+            guess = i32::max(guess, score);
+            if score >= beta {
+                best = i;
+                break;
+            }
+        }
+        if guess < beta {
+            upper = guess;
+        } else {
+            lower = guess;
+        }
+    }
+
+    moves[best].1 = lower;
+    publish(search.root_move(
+        board,
+        moves[best].0,
+        depth,
+        lower,
+        Bound::Exact,
+        nodes[best],
+    ));
+    // Keep the order of the others, they might be good guesses for the next depth
+    let best = moves.remove(best);
+    moves.insert(0, best);
+}
+
 fn minimax(
+    search: &Search,
     board: &Board,
-    table: &TransTable,
+    line: &Line,
+    depth: i16,
+    total_depth: i16,
+    alpha: i32,
+    beta: i32,
+) -> i32 {
+    search.traced(NodeKind::Pv, line.last_move(), depth, alpha, beta, || {
+        minimax_node(search, board, line, depth, total_depth, alpha, beta)
+    })
+}
+
+fn minimax_node(
+    search: &Search,
+    board: &Board,
+    line: &Line,
     depth: i16,
     total_depth: i16,
     mut alpha: i32,
     beta: i32,
 ) -> i32 {
-    let (hash, moves) = match init_search(board, table, depth, alpha, beta) {
+    let hash = match init_search(search, board, line, depth, alpha, beta) {
         Either::Left(score) => return score,
-        Either::Right(moves) => moves,
+        Either::Right(hash) => hash,
     };
 
-    let mut tmp = board.clone();
-    for (mov, _) in &moves {
-        board.make_move(*mov, &mut tmp);
-        let score = if *mov == moves[0].0 {
-            -minimax(&tmp, table, depth - 1, total_depth, -beta, -alpha)
+    // Without a hash move, the first move is just a guess; find a better one with a
+    // shallower search first, which puts its best move into the table
+    let mut best_move = search.table.get(hash).and_then(|e| e.best_move);
+    if best_move.is_none() && depth >= IID_DEPTH {
+        Stat::IIDSearches.inc();
+        minimax(
+            search,
+            board,
+            line,
+            depth - IID_REDUCTION,
+            total_depth,
+            alpha,
+            beta,
+        );
+        best_move = search.table.get(hash).and_then(|e| e.best_move);
+    }
+
+    let original_alpha = alpha;
+    let mut best = -INF;
+    let mut tmp = *board;
+    let moves = MovePicker::new(board, best_move, search.heuristics, line.ply());
+    for (i, mov) in moves.enumerate() {
+        board.make_move(mov, &mut tmp);
+        let child = line.child(board, mov, &tmp);
+        let score = if i == 0 {
+            -minimax(search, &tmp, &child, depth - 1, total_depth, -beta, -alpha)
         } else {
-            let score = -scout_search(&tmp, table, depth - 1, -alpha);
+            let score = -scout_search(search, &tmp, &child, depth - 1, total_depth, -alpha);
             if alpha < score && score < beta {
                 Stat::PVMisses.inc();
-                -minimax(&tmp, table, depth - 1, total_depth, -beta, -score)
+                -minimax(search, &tmp, &child, depth - 1, total_depth, -beta, -score)
             } else {
                 score
             }
         };
 
+        if search.stopped() {
+            return alpha;
+        }
+
         if score >= beta {
-            table.put(Entry {
+            search.table.put(Entry {
                 zobrist: hash,
-                score: score as i32,
+                score,
                 depth_of_score: depth,
                 depth_of_search: total_depth,
+                best_move: Some(mov),
+                bound: Bound::Lower,
             });
+            search
+                .heuristics
+                .record_cutoff(board, mov, line.ply(), depth);
             Stat::BranchesCut.inc();
-            return beta;
+            search.cutoff(Cutoff::Beta(mov));
+            return search.result(score, original_alpha, beta);
         }
 
+        if score > best {
+            best = score;
+        }
         if score > alpha {
             alpha = score;
+            best_move = Some(mov);
         }
     }
+    if best == -INF {
+        return no_moves(search, board, depth);
+    }
 
-    table.put(Entry {
+    search.table.put(Entry {
         zobrist: hash,
-        score: alpha,
+        score: best,
         depth_of_score: depth,
         depth_of_search: total_depth,
+        best_move,
+        bound: if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        },
     });
-    alpha
+    search.result(best, original_alpha, beta)
+}
+
+/// Zero-window search around `beta`: Only tells if the score is at least `beta`.
+fn scout_search(
+    search: &Search,
+    board: &Board,
+    line: &Line,
+    depth: i16,
+    total_depth: i16,
+    beta: i32,
+) -> i32 {
+    search.traced(
+        NodeKind::Scout,
+        line.last_move(),
+        depth,
+        beta - 1,
+        beta,
+        || scout_node(search, board, line, depth, total_depth, beta),
+    )
 }
 
-fn scout_search(board: &Board, table: &TransTable, depth: i16, beta: i32) -> i32 {
-    let (_, moves) = match init_search(board, table, depth, beta - 1, beta) {
+fn scout_node(
+    search: &Search,
+    board: &Board,
+    line: &Line,
+    depth: i16,
+    total_depth: i16,
+    beta: i32,
+) -> i32 {
+    let hash = match init_search(search, board, line, depth, beta - 1, beta) {
         Either::Left(score) => return score,
-        Either::Right(moves) => moves,
+        Either::Right(hash) => hash,
     };
 
-    let mut tmp = board.clone();
-    for (mov, _) in &moves {
-        board.make_move(*mov, &mut tmp);
-        let score = -scout_search(&tmp, table, depth - 1, 1 - beta);
+    if let Some(score) = probcut(search, board, line, depth, total_depth, beta) {
+        search.cutoff(Cutoff::ProbCut);
+        return search.result(score, beta - 1, beta);
+    }
+    let hash_move = search.table.get(hash).and_then(|e| e.best_move);
+    if let Some(score) = multi_cut(search, board, line, hash_move, depth, total_depth, beta) {
+        search.cutoff(Cutoff::MultiCut);
+        return search.result(score, beta - 1, beta);
+    }
+
+    let mut best = -INF;
+    let mut tmp = *board;
+    for mov in MovePicker::new(board, hash_move, search.heuristics, line.ply()) {
+        board.make_move(mov, &mut tmp);
+        let child = line.child(board, mov, &tmp);
+        let score = -scout_search(search, &tmp, &child, depth - 1, total_depth, 1 - beta);
+        if search.stopped() {
+            return beta - 1;
+        }
+
         if score >= beta {
-            return beta;
+            search.table.put(Entry {
+                zobrist: hash,
+                score,
+                depth_of_score: depth,
+                depth_of_search: total_depth,
+                best_move: Some(mov),
+                bound: Bound::Lower,
+            });
+            search
+                .heuristics
+                .record_cutoff(board, mov, line.ply(), depth);
+            search.cutoff(Cutoff::Beta(mov));
+            return search.result(score, beta - 1, beta);
         }
+        if score > best {
+            best = score;
+        }
+    }
+    if best == -INF {
+        return no_moves(search, board, depth);
     }
 
-    beta - 1
+    search.table.put(Entry {
+        zobrist: hash,
+        score: best,
+        depth_of_score: depth,
+        depth_of_search: total_depth,
+        best_move: hash_move,
+        bound: Bound::Upper,
+    });
+    search.result(best, beta - 1, beta)
 }
 
+/// ProbCut: If a good capture beats beta by a margin in a shallow search,
+/// the full search would very likely fail high as well.
+fn probcut(
+    search: &Search,
+    board: &Board,
+    line: &Line,
+    depth: i16,
+    total_depth: i16,
+    beta: i32,
+) -> Option<i32> {
+    let probcut = search.settings.probcut?;
+    if depth < probcut.depth || beta.abs() >= WIN {
+        return None;
+    }
+
+    let raised_beta = beta + probcut.margin;
+    let shallow_depth = i16::max(0, depth - 1 - probcut.reduction);
+    let mut tmp = *board;
+    for mov in MovePicker::captures(board) {
+        if !evaluation::is_good_capture(board, mov) {
+            // Bad captures are always last
+            break;
+        }
+        board.make_move(mov, &mut tmp);
+        let child = line.child(board, mov, &tmp);
+        let score = -scout_search(
+            search,
+            &tmp,
+            &child,
+            shallow_depth,
+            total_depth,
+            1 - raised_beta,
+        );
+        if score >= raised_beta && !search.stopped() {
+            Stat::ProbCutPrunes.inc();
+            return Some(score - probcut.margin);
+        }
+    }
+    None
+}
+
+/// Multi-cut: If several of the first moves beat beta in a reduced search,
+/// at least one of them would very likely do so in the full search.
+/// Returns the lowest of their scores, which is at least beta.
+fn multi_cut(
+    search: &Search,
+    board: &Board,
+    line: &Line,
+    hash_move: Option<ChessMove>,
+    depth: i16,
+    total_depth: i16,
+    beta: i32,
+) -> Option<i32> {
+    let multi_cut = match search.settings.multi_cut {
+        Some(multi_cut) if depth >= multi_cut.depth => multi_cut,
+        _ => return None,
+    };
+
+    let shallow_depth = i16::max(0, depth - 1 - multi_cut.reduction);
+    let mut cutoffs = 0;
+    let mut lowest = INF;
+    let mut tmp = *board;
+    let moves = MovePicker::new(board, hash_move, search.heuristics, line.ply());
+    for mov in moves.take(multi_cut.moves) {
+        board.make_move(mov, &mut tmp);
+        let child = line.child(board, mov, &tmp);
+        let score = -scout_search(search, &tmp, &child, shallow_depth, total_depth, 1 - beta);
+        if score >= beta && !search.stopped() {
+            cutoffs += 1;
+            lowest = i32::min(lowest, score);
+            if cutoffs >= multi_cut.cutoffs {
+                Stat::MultiCutPrunes.inc();
+                return Some(lowest);
+            }
+        }
+    }
+    None
+}
+
+/// Handles everything that does not need to look at the moves of a node.
+/// Returns either the final score of the node or its hash.
 fn init_search(
+    search: &Search,
     board: &Board,
-    table: &TransTable,
+    line: &Line,
     depth: i16,
     alpha: i32,
     beta: i32,
-) -> Either<i32, (u64, Vec<RatedMove>)> {
+) -> Either<i32, u64> {
+    if search.stopped() {
+        search.cutoff(Cutoff::Stopped);
+        return Either::Left(0);
+    }
+    search.count_node();
+
+    // Needs to be checked before the table, which does not know how a position was reached
+    if line.is_draw(board) {
+        search.cutoff(Cutoff::Draw);
+        return Either::Left(search.draw_score(board));
+    }
+
     let hash = board.get_hash();
-    match table.get(hash) {
-        Some(entry) if entry.depth_of_score >= depth => {
+    let entry = search.table.get(hash);
+    if entry.is_some() {
+        search.table_hit();
+    }
+    match entry {
+        Some(entry)
+            if entry.depth_of_score >= depth && entry.bound.cuts(entry.score, alpha, beta) =>
+        {
             Stat::TableHits.inc();
-            return Either::Left(entry.score);
+            search.cutoff(Cutoff::Table);
+            return Either::Left(search.result(entry.score, alpha, beta));
         }
         _ => Stat::TableMisses.inc(),
     }
 
     if depth == 0 {
         Stat::NodesEvaluated.inc();
-        return Either::Left(explore_captures(board, table, alpha, beta));
+        return Either::Left(captures_node(search, board, alpha, beta));
     }
+    Either::Right(hash)
+}
 
-    let moves = ai::sorted_moves(board, &table);
-    match moves.len() {
-        0 if board.checkers() != &EMPTY => {
-            // Lost
-            Stat::CheckmatesFound.inc();
-            Either::Left(-(WIN + (depth as i32 * 1024)))
-        }
-        0 => Either::Left(-WIN / 2), // Stalemate
-        _ => Either::Right((hash, moves)),
+/// The score of a node without legal moves, found when the move picker had none.
+fn no_moves(search: &Search, board: &Board, depth: i16) -> i32 {
+    if board.checkers() != &EMPTY {
+        // Lost
+        Stat::CheckmatesFound.inc();
+        search.cutoff(Cutoff::Checkmate);
+        -(WIN + (depth as i32 * 1024))
+    } else {
+        search.cutoff(Cutoff::Stalemate);
+        search.draw_score(board)
     }
 }
 
-fn explore_captures(board: &Board, table: &TransTable, mut alpha: i32, beta: i32) -> i32 {
-    let score = evaluation::eval_board(board, table);
-    if score >= beta {
-        return beta;
+fn explore_captures(search: &Search, board: &Board, mov: ChessMove, alpha: i32, beta: i32) -> i32 {
+    search.traced(NodeKind::Quiescence, Some(mov), 0, alpha, beta, || {
+        captures_node(search, board, alpha, beta)
+    })
+}
+
+fn captures_node(search: &Search, board: &Board, mut alpha: i32, beta: i32) -> i32 {
+    let original_alpha = alpha;
+    let mut best = evaluation::eval_board(board, search.table, search.settings.evaluator);
+    if best >= beta {
+        search.cutoff(Cutoff::StandPat);
+        return search.result(best, original_alpha, beta);
     }
-    if score > alpha {
-        alpha = score;
+    if best > alpha {
+        alpha = best;
     }
 
-    let moves = ai::capturing_moves(board, &table);
-    let mut tmp = board.clone();
-    for (mov, _) in &moves {
-        board.make_move(*mov, &mut tmp);
-        let score = if *mov == moves[0].0 {
-            -explore_captures(&tmp, table, -beta, -alpha)
+    let mut tmp = *board;
+    for (i, mov) in MovePicker::captures(board).enumerate() {
+        search.count_node();
+        board.make_move(mov, &mut tmp);
+        let score = if i == 0 {
+            -explore_captures(search, &tmp, mov, -beta, -alpha)
         } else {
-            let score = -explore_captures(&tmp, table, -alpha - 1, -alpha);
+            let score = -explore_captures(search, &tmp, mov, -alpha - 1, -alpha);
             if alpha < score && score < beta {
                 Stat::PVMisses.inc();
-                -explore_captures(&tmp, table, -beta, -score)
+                -explore_captures(search, &tmp, mov, -beta, -score)
             } else {
                 score
             }
@@ -210,24 +993,416 @@ fn explore_captures(board: &Board, table: &TransTable, mut alpha: i32, beta: i32
 
         if score >= beta {
             Stat::BranchesCut.inc();
-            return beta;
+            search.cutoff(Cutoff::Beta(mov));
+            return search.result(score, original_alpha, beta);
         }
 
+        if score > best {
+            best = score;
+        }
         if score > alpha {
             alpha = score;
         }
     }
 
-    alpha
+    search.result(best, original_alpha, beta)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::calculate_move_until_depth;
-    use chess::Board;
+    use super::{
+        calc_depth, calculate_move_until_depth, mtdf_depth, search_nodes, search_until_depth,
+        with_test_search, Pvs, RootDriver, START_DEPTH,
+    };
+    use crate::ai::ordering::Heuristics;
+    use crate::ai::searcher::{Info, Limits, Searcher, SharedState};
+    use crate::ai::settings::{MultiCut, ProbCut, Settings};
+    use crate::ai::table::{Bound, TransTable};
+    use crate::ai::tree::{Cutoff, NodeKind, SearchTree};
+    use chess::{Board, ChessMove, MoveGen, Square};
+    use rayon::ThreadPoolBuilder;
     use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
     use test::Bencher;
 
+    #[test]
+    fn test_fail_soft_matches_fail_hard() {
+        let positions = [
+            (
+                "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+                Square::D1,
+                Square::D8,
+            ),
+            (
+                "3r2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1",
+                Square::D8,
+                Square::D1,
+            ),
+            ("k7/8/1K6/8/8/8/8/7R w - - 0 1", Square::H1, Square::H8),
+            (
+                "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+                Square::H5,
+                Square::F7,
+            ),
+        ];
+        let settings = Settings {
+            probcut: None,
+            ..Settings::default()
+        };
+        // A single thread, so both searches see the table in the same state
+        let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        for (fen, from, to) in positions.iter() {
+            let board = Board::from_str(fen).unwrap();
+            let soft = pool.install(|| search_until_depth(board, 3, settings, false));
+            let hard = pool.install(|| search_until_depth(board, 3, settings, true));
+            assert_eq!(soft, hard, "{}", fen);
+            assert_eq!(soft, ChessMove::new(*from, *to, None), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_mtdf_matches_pvs() {
+        let positions = [
+            "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+            "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+        ];
+        let settings = Settings {
+            probcut: None,
+            ..Settings::default()
+        };
+        let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        for fen in positions.iter() {
+            let board = Board::from_str(fen).unwrap();
+            let search = |driver: RootDriver| {
+                pool.install(|| {
+                    with_test_search(&board, settings, false, |search| {
+                        let mut moves = crate::ai::sorted_moves(&board, search.table);
+                        for depth in 2..=3 {
+                            driver(search, board, depth, &mut moves, &|_| ());
+                        }
+                        moves[0]
+                    })
+                })
+            };
+            assert_eq!(search(mtdf_depth), search(calc_depth), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_publish_during_depth() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let mate = ChessMove::new(Square::D1, Square::D8, None);
+        let published = Mutex::new(Vec::new());
+        let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        pool.install(|| {
+            with_test_search(&board, Settings::default(), false, |search| {
+                // The mate is not searched first, so it is only found during the depth
+                let mut moves = MoveGen::new_legal(&board)
+                    .map(|m| (m, if m == mate { -1 } else { 0 }))
+                    .collect::<Vec<_>>();
+                moves.sort_by_key(|m| -m.1);
+                calc_depth(search, board, 2, &mut moves, &|info| {
+                    if let Info::BestMove { depth, mov, score } = info {
+                        assert_eq!(depth, 2);
+                        published.lock().unwrap().push((mov, score))
+                    }
+                });
+            })
+        });
+        let published = published.into_inner().unwrap();
+        assert_eq!(published.last().map(|p| p.0), Some(mate));
+        assert!(published.last().unwrap().1 > 900000);
+        assert!(published.len() > 1);
+    }
+
+    #[test]
+    fn test_root_moves() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let mate = ChessMove::new(Square::D1, Square::D8, None);
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        for driver in [calc_depth as RootDriver, mtdf_depth] {
+            let infos = Mutex::new(Vec::new());
+            let nodes = pool.install(|| {
+                with_test_search(&board, Settings::default(), false, |search| {
+                    let mut moves = crate::ai::sorted_moves(&board, search.table);
+                    driver(search, board, 3, &mut moves, &|info| {
+                        infos.lock().unwrap().push(info)
+                    });
+                    search.nodes.load(std::sync::atomic::Ordering::Relaxed)
+                })
+            });
+
+            let infos = infos.into_inner().unwrap();
+            let mut numbers = infos
+                .iter()
+                .filter_map(|info| match info {
+                    Info::CurrentMove { depth, number, .. } => {
+                        assert_eq!(*depth, 3);
+                        Some(*number)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            numbers.sort_unstable();
+            numbers.dedup();
+            assert_eq!(numbers[0], 1);
+
+            let root_moves = infos
+                .iter()
+                .filter_map(|info| match info {
+                    Info::RootMove(root_move) => Some(root_move),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for root_move in &root_moves {
+                assert!(root_move.nodes <= nodes);
+                assert_eq!(root_move.depth, 3);
+                assert_eq!(root_move.pv[0], root_move.mov);
+                assert!(board.legal(root_move.mov));
+            }
+            let best = root_moves
+                .iter()
+                .find(|r| r.mov == mate && r.bound == Bound::Exact)
+                .unwrap();
+            assert!(best.score > 900000);
+            assert!(best.nodes > 0);
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let board =
+            Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
+                .unwrap();
+        let search = || {
+            search_nodes(
+                &TransTable::new(),
+                &Heuristics::new(),
+                board,
+                &[],
+                Settings::default(),
+                20000,
+                None,
+                &[],
+                calc_depth,
+                &mut |_| (),
+            )
+        };
+        let first = search();
+        // The number of threads available must not matter
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let second = pool.install(search);
+        assert_eq!(first, second);
+        assert!(first.depth >= 2);
+        assert!(first.nodes >= 20000);
+        assert_eq!(first.pv[0], first.best);
+        assert!(board.legal(first.best));
+    }
+
+    #[test]
+    fn test_search_tree() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let tree = SearchTree::new(2);
+        let table = TransTable::new();
+        let settings = Settings::default();
+        search_nodes(
+            &table,
+            &Heuristics::new(),
+            board,
+            &[],
+            settings,
+            5000,
+            Some(&tree),
+            &[],
+            calc_depth,
+            &mut |_| (),
+        );
+
+        let nodes = tree.nodes();
+        assert!(!nodes.is_empty());
+        for (id, node) in nodes.iter().enumerate() {
+            match node.parent {
+                None => assert!(board.legal(node.mov.unwrap())),
+                // At most two plies deep
+                Some(parent) => {
+                    assert!(parent < id);
+                    assert_eq!(nodes[parent].parent, None);
+                }
+            }
+        }
+        let mate = ChessMove::new(Square::D1, Square::D8, None);
+        assert!(nodes
+            .iter()
+            .any(|n| n.mov == Some(mate) && n.parent.is_none() && -n.score > 900000));
+    }
+
+    #[test]
+    fn test_pvs_searcher() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+        let state = SharedState {
+            table: Arc::new(TransTable::new()),
+            heuristics: Arc::new(Heuristics::new()),
+            tree: None,
+        };
+        let settings = Settings::default();
+        let limits = Limits {
+            time: 0.0,
+            nodes: Some(5000),
+            search_moves: Vec::new(),
+        };
+        let mut depths = Vec::new();
+        let report = Pvs.search(&state, board, &[], settings, limits, &mut |info| {
+            if let Info::Depth { depth, .. } = info {
+                depths.push(*depth)
+            }
+        });
+        assert_eq!(report.best, ChessMove::new(Square::D1, Square::D8, None));
+        // A best move found during the interrupted depth is reported with that depth
+        let last = *depths.last().unwrap();
+        assert!(report.depth == last || report.depth == last + 1);
+        assert_eq!(depths[0], 2);
+        // Moves of an interrupted depth may come before the best move of the last complete one
+        assert!(report
+            .root_moves
+            .iter()
+            .any(|r| r.mov == report.best && r.depth >= report.depth));
+        assert!(report
+            .root_moves
+            .windows(2)
+            .all(|w| (w[0].depth, w[0].score) >= (w[1].depth, w[1].score)));
+    }
+
+    #[test]
+    fn test_decided_root() {
+        // Every move keeps the material insufficient, so there is nothing to search deeper
+        let board = Board::from_str("8/8/4k3/8/8/2KB4/8/8 w - - 0 1").unwrap();
+        let state = SharedState {
+            table: Arc::new(TransTable::new()),
+            heuristics: Arc::new(Heuristics::new()),
+            tree: None,
+        };
+        let by_time = Limits {
+            time: 40.0,
+            nodes: None,
+            search_moves: Vec::new(),
+        };
+        let by_nodes = Limits {
+            time: 0.0,
+            nodes: Some(u64::MAX),
+            search_moves: Vec::new(),
+        };
+        for limits in [by_time, by_nodes] {
+            let start = Instant::now();
+            let report = Pvs.search(&state, board, &[], Settings::default(), limits, &mut |_| ());
+            assert!(start.elapsed().as_secs_f32() < 5.0);
+            assert_eq!(report.depth, START_DEPTH);
+            assert_eq!(report.score, 0);
+        }
+    }
+
+    #[test]
+    fn test_probcut() {
+        // Nc7+ forks king and rook
+        let board = Board::from_str("r3k3/pp3ppp/8/3N4/8/8/PP3PPP/4K3 w - - 0 1").unwrap();
+        let fork = ChessMove::new(Square::D5, Square::C7, None);
+        let search = |probcut| {
+            let tree = SearchTree::new(64);
+            let settings = Settings {
+                probcut,
+                ..Settings::default()
+            };
+            let report = search_nodes(
+                &TransTable::new(),
+                &Heuristics::new(),
+                board,
+                &[],
+                settings,
+                200000,
+                Some(&tree),
+                &[],
+                calc_depth,
+                &mut |_| (),
+            );
+            (report, tree.nodes())
+        };
+
+        let (pruned, nodes) = search(Some(ProbCut::default()));
+        let probcuts = nodes
+            .iter()
+            .filter(|n| n.cutoff == Some(Cutoff::ProbCut))
+            .collect::<Vec<_>>();
+        assert!(!probcuts.is_empty());
+        // Only zero window nodes are pruned
+        assert!(probcuts.iter().all(|n| n.kind == NodeKind::Scout));
+        assert_eq!(pruned.best, fork);
+
+        let (full, nodes) = search(None);
+        assert!(nodes.iter().all(|n| n.cutoff != Some(Cutoff::ProbCut)));
+        assert_eq!(full.best, fork);
+        assert!(pruned.depth >= 6);
+    }
+
+    #[test]
+    fn test_multi_cut() {
+        let board = Board::from_str("r3k3/pp3ppp/8/3N4/8/8/PP3PPP/4K3 w - - 0 1").unwrap();
+        let tree = SearchTree::new(64);
+        let settings = Settings {
+            multi_cut: Some(MultiCut::default()),
+            ..Settings::default()
+        };
+        let report = search_nodes(
+            &TransTable::new(),
+            &Heuristics::new(),
+            board,
+            &[],
+            settings,
+            200000,
+            Some(&tree),
+            &[],
+            calc_depth,
+            &mut |_| (),
+        );
+        assert_eq!(report.best, ChessMove::new(Square::D5, Square::C7, None));
+        let cuts = tree
+            .nodes()
+            .into_iter()
+            .filter(|n| n.cutoff == Some(Cutoff::MultiCut))
+            .collect::<Vec<_>>();
+        assert!(!cuts.is_empty());
+        // Fail-soft: The score of the reduced searches, not just beta
+        assert!(cuts.iter().all(|n| n.score >= n.beta));
+        assert!(cuts.iter().any(|n| n.score > n.beta));
+    }
+
+    #[test]
+    fn test_depth_of_search() {
+        let board =
+            Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
+                .unwrap();
+        let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        pool.install(|| {
+            with_test_search(&board, Settings::default(), false, |search| {
+                let mut moves = crate::ai::sorted_moves(&board, search.table);
+                calc_depth(search, board, 4, &mut moves, &|_| ());
+                // Replies are searched by both full and zero window nodes
+                let mut entries = 0;
+                for mov in MoveGen::new_legal(&board) {
+                    let after = board.make_move_new(mov);
+                    for reply in MoveGen::new_legal(&after) {
+                        let hash = after.make_move_new(reply).get_hash();
+                        if let Some(entry) = search.table.get(hash) {
+                            assert_eq!(entry.depth_of_search, 4);
+                            entries += 1;
+                        }
+                    }
+                }
+                assert!(entries > 0);
+            })
+        });
+    }
+
     #[bench]
     fn bench_depth_3(b: &mut Bencher) {
         let board =
@@ -235,4 +1410,28 @@ mod tests {
                 .unwrap();
         b.iter(|| calculate_move_until_depth(board, 3));
     }
+
+    #[bench]
+    fn bench_mtdf_depth_4(b: &mut Bencher) {
+        let board =
+            Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
+                .unwrap();
+        b.iter(|| {
+            with_test_search(&board, Settings::default(), false, |search| {
+                let mut moves = crate::ai::sorted_moves(&board, search.table);
+                for depth in 2..=4 {
+                    mtdf_depth(search, board, depth, &mut moves, &|_| ());
+                }
+                moves[0].0
+            })
+        });
+    }
+
+    #[bench]
+    fn bench_depth_4(b: &mut Bencher) {
+        let board =
+            Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
+                .unwrap();
+        b.iter(|| calculate_move_until_depth(board, 4));
+    }
 }
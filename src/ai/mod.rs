@@ -1,43 +1,60 @@
+mod distributed;
+mod draw;
+mod engine;
 mod evaluation;
+mod mate;
+mod mcts;
 mod minimax;
 mod nnue;
+mod ordering;
+mod pawns;
+mod problem;
+mod pst;
+mod searcher;
+mod settings;
 mod statistics;
 mod table;
+mod threat;
+mod time;
+mod tree;
 
-use crate::ai::statistics::Stat;
-use crate::ai::table::TransTable;
 use chess::{BitBoard, Board, ChessMove, Color, MoveGen, Rank};
-use rayon::slice::ParallelSliceMut;
+pub use distributed::{run_worker, Coordinator};
+pub use draw::Contempt;
+pub use engine::Engine;
+pub use mate::MateSolver;
+pub use mcts::Mcts;
+pub use minimax::{Mtdf, Pvs};
+pub use ordering::Heuristics;
+pub use problem::{ProblemSolver, Solutions, Stipulation};
+pub use searcher::{Info, Limits, Report, RootMove, Searcher, SharedState};
+pub use settings::{Deterministic, Evaluator, MultiCut, ProbCut, Settings};
+pub use table::{Bound, Entry, TransTable};
+pub use threat::Threat;
+pub use tree::{Cutoff, Node, NodeKind, SearchTree};
 
 type RatedMove = (ChessMove, i32);
 
 /// Calculate the best possible move, using `time` amount of time.
 /// Actual time spent will be slightly higher (maybe about 100ms? unmeasured).
+/// Use an [`Engine`] instead to keep search state between moves of a game.
 pub fn get_best_move(board: Board, time: f32) -> ChessMove {
-    nnue::init();
-    let mov = minimax::calculate_move(board, time);
-    Stat::log();
-    mov
+    Engine::new().best_move(board, time)
 }
 
 /// Sorts all possible moves by their basic evaluation. (best first)
+/// Only used at the root; nodes inside the tree use a lazy `MovePicker`.
+/// The best move stored in the transposition table for this position always comes first.
 fn sorted_moves(board: &Board, table: &TransTable) -> Vec<RatedMove> {
     let gen = MoveGen::new_legal(board);
+    let hash_move = table.get(board.get_hash()).and_then(|e| e.best_move);
     let mut moves = gen
-        .map(|m| (m, evaluation::eval_move(board, table, m)))
+        .map(|m| match hash_move {
+            Some(hash_move) if hash_move == m => (m, i32::MAX),
+            _ => (m, evaluation::eval_move(board, table, m)),
+        })
         .collect::<Vec<_>>();
-    moves.par_sort_unstable_by_key(|mov| -mov.1);
-    moves
-}
-
-/// Sorts all capturing moves by their basic evaluation. (best first)
-fn capturing_moves(board: &Board, table: &TransTable) -> Vec<RatedMove> {
-    let mut gen = MoveGen::new_legal(board);
-    gen.set_iterator_mask(*board.color_combined(!board.side_to_move()));
-    let mut moves = gen
-        .map(|m| (m, evaluation::eval_move(board, table, m)))
-        .collect::<Vec<_>>();
-    moves.par_sort_unstable_by_key(|mov| -mov.1);
+    moves.sort_unstable_by_key(|mov| -mov.1);
     moves
 }
 
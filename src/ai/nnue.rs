@@ -1,9 +1,20 @@
 use chess::{BitBoard, Board, Color, Piece, NUM_PIECES};
 use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub fn init() {
+/// If a network was loaded; evaluating without one gives meaningless scores.
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// Load the network from `model.nnue`, returning if that worked.
+pub fn init() -> bool {
     let path = CString::new("model.nnue").unwrap();
-    assert!(unsafe { probe::nnue_init(path.as_ptr()) })
+    let ready = unsafe { probe::nnue_init(path.as_ptr()) };
+    READY.fetch_or(ready, Ordering::Relaxed);
+    ready
+}
+
+pub fn is_ready() -> bool {
+    READY.load(Ordering::Relaxed)
 }
 
 pub fn eval(board: &Board) -> i32 {
@@ -71,7 +82,7 @@ mod tests {
 
     #[test]
     fn test_identical() {
-        init();
+        assert!(init());
         let board =
             Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
                 .unwrap();
@@ -80,7 +91,7 @@ mod tests {
 
     #[bench]
     fn bench_eval(b: &mut Bencher) {
-        init();
+        assert!(init());
         let board =
             Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
                 .unwrap();
@@ -89,7 +100,7 @@ mod tests {
 
     #[bench]
     fn bench_eval_fen(b: &mut Bencher) {
-        init();
+        assert!(init());
         let board =
             Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
                 .unwrap();
@@ -0,0 +1,314 @@
+use crate::ai::evaluation;
+use crate::ai::table::{pack_move, unpack_move};
+use chess::{Board, ChessMove, MoveGen, EMPTY};
+use std::sync::atomic::{AtomicI32, AtomicU16, Ordering};
+
+/// More than the maximum amount of legal moves in any position (218).
+const MAX_MOVES: usize = 256;
+const MAX_PLY: usize = 128;
+const HISTORY_LIMIT: i32 = 1 << 20;
+
+/// Move ordering knowledge gathered during search: killer moves per ply
+/// and the history heuristic. Shared by all search threads without locking.
+pub struct Heuristics {
+    killers: Vec<[AtomicU16; 2]>,
+    history: Vec<AtomicI32>,
+}
+
+impl Heuristics {
+    /// Prepare for a new search: killers are specific to a ply from the root,
+    /// history is kept but weighted less.
+    pub fn new_search(&self) {
+        for killers in &self.killers {
+            killers[0].store(0, Ordering::Relaxed);
+            killers[1].store(0, Ordering::Relaxed);
+        }
+        for history in &self.history {
+            history.store(history.load(Ordering::Relaxed) / 2, Ordering::Relaxed);
+        }
+    }
+
+    /// Remember a quiet move that caused a beta cutoff.
+    pub fn record_cutoff(&self, board: &Board, mov: ChessMove, ply: usize, depth: i16) {
+        if !is_quiet(board, mov) {
+            return;
+        }
+        if let Some(killers) = self.killers.get(ply) {
+            let packed = pack_move(Some(mov));
+            if killers[0].load(Ordering::Relaxed) != packed {
+                killers[1].store(killers[0].load(Ordering::Relaxed), Ordering::Relaxed);
+                killers[0].store(packed, Ordering::Relaxed);
+            }
+        }
+
+        let history = &self.history[history_index(mov)];
+        let bonus = depth as i32 * depth as i32;
+        if history.fetch_add(bonus, Ordering::Relaxed) + bonus > HISTORY_LIMIT {
+            history.store(HISTORY_LIMIT / 2, Ordering::Relaxed);
+        }
+    }
+
+    fn killers(&self, ply: usize) -> [Option<ChessMove>; 2] {
+        match self.killers.get(ply) {
+            Some(killers) => [
+                unpack_move(killers[0].load(Ordering::Relaxed)),
+                unpack_move(killers[1].load(Ordering::Relaxed)),
+            ],
+            None => [None, None],
+        }
+    }
+
+    fn history(&self, mov: ChessMove) -> i32 {
+        self.history[history_index(mov)].load(Ordering::Relaxed)
+    }
+
+    pub fn new() -> Self {
+        Self {
+            killers: (0..MAX_PLY).map(|_| Default::default()).collect(),
+            history: (0..64 * 64).map(|_| AtomicI32::new(0)).collect(),
+        }
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn history_index(mov: ChessMove) -> usize {
+    mov.get_source().to_index() * 64 + mov.get_dest().to_index()
+}
+
+fn is_quiet(board: &Board, mov: ChessMove) -> bool {
+    board.piece_on(mov.get_dest()).is_none() && mov.get_promotion().is_none()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Stage {
+    HashMove,
+    GenCaptures,
+    GoodCaptures,
+    Killers(usize),
+    GenQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Produces the legal moves of a position in stages, best first:
+/// hash move, good captures, killers, quiet moves, bad captures.
+/// Moves are only generated and scored once a stage is reached, and
+/// never allocates, so a cutoff by an early move saves most of the work.
+pub struct MovePicker<'a> {
+    board: &'a Board,
+    gen: MoveGen,
+    stage: Stage,
+    hash_move: Option<ChessMove>,
+    killers: [Option<ChessMove>; 2],
+    heuristics: Option<&'a Heuristics>,
+    /// Good captures and quiets are picked from the front, bad captures are kept at the back
+    moves: [(ChessMove, i32); MAX_MOVES],
+    next: usize,
+    end: usize,
+    bad_start: usize,
+}
+
+impl<'a> MovePicker<'a> {
+    /// All legal moves, for a node at `ply` from the root.
+    pub fn new(
+        board: &'a Board,
+        hash_move: Option<ChessMove>,
+        heuristics: &'a Heuristics,
+        ply: usize,
+    ) -> Self {
+        Self {
+            hash_move: hash_move.filter(|m| board.legal(*m)),
+            killers: heuristics.killers(ply),
+            heuristics: Some(heuristics),
+            ..Self::captures(board)
+        }
+    }
+
+    /// Only capturing moves, for quiescence search.
+    pub fn captures(board: &'a Board) -> Self {
+        Self {
+            board,
+            gen: MoveGen::new_legal(board),
+            stage: Stage::HashMove,
+            hash_move: None,
+            killers: [None, None],
+            heuristics: None,
+            moves: [(ChessMove::default(), 0); MAX_MOVES],
+            next: 0,
+            end: 0,
+            bad_start: MAX_MOVES,
+        }
+    }
+
+    fn is_special(&self, mov: ChessMove) -> bool {
+        self.hash_move == Some(mov) || self.killers.contains(&Some(mov))
+    }
+
+    /// Move the best remaining move in `next..end` to `next` and return it.
+    fn pick_best(&mut self) -> Option<ChessMove> {
+        if self.next >= self.end {
+            return None;
+        }
+        let mut best = self.next;
+        for i in self.next + 1..self.end {
+            if self.moves[i].1 > self.moves[best].1 {
+                best = i;
+            }
+        }
+        self.moves.swap(self.next, best);
+        self.next += 1;
+        Some(self.moves[self.next - 1].0)
+    }
+}
+
+impl Iterator for MovePicker<'_> {
+    type Item = ChessMove;
+
+    fn next(&mut self) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenCaptures;
+                    if self.hash_move.is_some() {
+                        return self.hash_move;
+                    }
+                }
+                Stage::GenCaptures => {
+                    self.gen
+                        .set_iterator_mask(*self.board.color_combined(!self.board.side_to_move()));
+                    for mov in &mut self.gen {
+                        if self.hash_move == Some(mov) {
+                            continue;
+                        }
+                        let score = evaluation::eval_capture(self.board, mov);
+                        if evaluation::is_good_capture(self.board, mov) {
+                            self.moves[self.end] = (mov, score);
+                            self.end += 1;
+                        } else {
+                            self.bad_start -= 1;
+                            self.moves[self.bad_start] = (mov, score);
+                        }
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match self.pick_best() {
+                    Some(mov) => return Some(mov),
+                    None if self.heuristics.is_some() => self.stage = Stage::Killers(0),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::Killers(i) if i < self.killers.len() => {
+                    self.stage = Stage::Killers(i + 1);
+                    match self.killers[i] {
+                        Some(killer)
+                            if self.hash_move != Some(killer)
+                                && is_quiet(self.board, killer)
+                                && self.board.legal(killer) =>
+                        {
+                            return Some(killer)
+                        }
+                        _ => (),
+                    }
+                }
+                Stage::Killers(_) => self.stage = Stage::GenQuiets,
+                Stage::GenQuiets => {
+                    let heuristics = self.heuristics.unwrap();
+                    self.gen.set_iterator_mask(!EMPTY);
+                    while let Some(mov) = self.gen.next() {
+                        if self.is_special(mov) {
+                            continue;
+                        }
+                        let score =
+                            heuristics.history(mov) + evaluation::eval_move_static(self.board, mov);
+                        self.moves[self.end] = (mov, score);
+                        self.end += 1;
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.pick_best() {
+                    Some(mov) => return Some(mov),
+                    None => {
+                        self.next = self.bad_start;
+                        self.end = MAX_MOVES;
+                        self.stage = Stage::BadCaptures;
+                    }
+                },
+                Stage::BadCaptures => {
+                    if self.next < self.bad_start {
+                        // Coming from good captures in a capture-only picker
+                        self.next = self.bad_start;
+                        self.end = MAX_MOVES;
+                    }
+                    match self.pick_best() {
+                        Some(mov) => return Some(mov),
+                        None => self.stage = Stage::Done,
+                    }
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Heuristics, MovePicker};
+    use crate::ai;
+    use crate::ai::table::TransTable;
+    use chess::{Board, ChessMove, MoveGen, Square};
+    use std::collections::HashSet;
+    use std::str::FromStr;
+    use test::Bencher;
+
+    const FEN: &str = "r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8";
+
+    #[test]
+    fn test_all_moves_once() {
+        let board = Board::from_str(FEN).unwrap();
+        let heuristics = Heuristics::new();
+        let hash_move = ChessMove::new(Square::C6, Square::D4, None);
+        heuristics.record_cutoff(&board, ChessMove::new(Square::E8, Square::G8, None), 3, 4);
+        // Not legal here, must be skipped
+        heuristics.record_cutoff(&board, ChessMove::new(Square::A7, Square::A4, None), 3, 4);
+
+        let picked = MovePicker::new(&board, Some(hash_move), &heuristics, 3).collect::<Vec<_>>();
+        let legal = MoveGen::new_legal(&board).collect::<HashSet<_>>();
+        assert_eq!(picked.len(), legal.len());
+        assert_eq!(picked.iter().collect::<HashSet<_>>().len(), legal.len());
+        assert_eq!(picked[0], hash_move);
+        assert_eq!(picked[1], ChessMove::new(Square::E8, Square::G8, None));
+        // Both captures risk a piece for a pawn, so they come last
+        let n = picked.len();
+        assert_eq!(picked[n - 2], ChessMove::new(Square::F6, Square::D5, None));
+        assert_eq!(picked[n - 1], ChessMove::new(Square::D8, Square::D5, None));
+    }
+
+    #[test]
+    fn test_captures_only() {
+        let board = Board::from_str(FEN).unwrap();
+        let picked = MovePicker::captures(&board).collect::<Vec<_>>();
+        assert_eq!(picked.len(), 2);
+        assert!(picked
+            .iter()
+            .all(|m| board.piece_on(m.get_dest()).is_some()));
+    }
+
+    #[bench]
+    fn bench_move_picker(b: &mut Bencher) {
+        let board = Board::from_str(FEN).unwrap();
+        let heuristics = Heuristics::new();
+        b.iter(|| MovePicker::new(&board, None, &heuristics, 0).count());
+    }
+
+    #[bench]
+    fn bench_sorted_moves(b: &mut Bencher) {
+        let board = Board::from_str(FEN).unwrap();
+        let table = TransTable::new();
+        b.iter(|| ai::sorted_moves(&board, &table).len());
+    }
+}
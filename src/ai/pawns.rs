@@ -0,0 +1,323 @@
+use crate::ai::evaluation::Tapered;
+use crate::ai::statistics::Stat;
+use chess::{
+    get_adjacent_files, get_file, get_pawn_attacks, BitBoard, Board, Color, Piece, Square, EMPTY,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const CAPACITY: usize = usize::pow(2, 14);
+const MASK: usize = CAPACITY - 1;
+
+const DOUBLED: Tapered = Tapered::new(-10, -20);
+const ISOLATED: Tapered = Tapered::new(-10, -15);
+const BACKWARD: Tapered = Tapered::new(-8, -10);
+/// Per pawn defended by or side by side with another pawn, plus `CONNECTED_RANK` per rank
+const CONNECTED: Tapered = Tapered::new(6, 4);
+const CONNECTED_RANK: Tapered = Tapered::new(2, 2);
+/// Passed pawns by rank from their side's point of view
+const PASSED: [Tapered; 8] = [
+    Tapered::new(0, 0),
+    Tapered::new(5, 10),
+    Tapered::new(10, 20),
+    Tapered::new(15, 35),
+    Tapered::new(30, 60),
+    Tapered::new(50, 100),
+    Tapered::new(80, 150),
+    Tapered::new(0, 0),
+];
+/// Pawns in front of the king, one and two ranks ahead; only while pieces can attack the king
+const SHIELD: [Tapered; 2] = [Tapered::new(12, 0), Tapered::new(6, 0)];
+
+/// Caches the parts of the pawn structure evaluation that only depend on the pawns,
+/// keyed by a hash of the pawns alone, so they are reused in every position with the
+/// same pawns. Lock-less like the transposition table: the key is stored XORed with the data.
+pub struct PawnTable {
+    slots: Vec<PawnSlot>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PawnEntry {
+    /// Score for white
+    score: Tapered,
+    /// Passed pawns of both sides
+    passed: BitBoard,
+}
+
+#[derive(Default)]
+struct PawnSlot {
+    /// `hash ^ score ^ passed`
+    key: AtomicU64,
+    score: AtomicU64,
+    passed: AtomicU64,
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        Self {
+            slots: (0..CAPACITY).map(|_| PawnSlot::default()).collect(),
+        }
+    }
+
+    fn get(&self, hash: u64) -> Option<PawnEntry> {
+        let slot = &self.slots[hash as usize & MASK];
+        let key = slot.key.load(Ordering::Relaxed);
+        let score = slot.score.load(Ordering::Relaxed);
+        let passed = slot.passed.load(Ordering::Relaxed);
+        if key ^ score ^ passed != hash {
+            return None;
+        }
+        Some(PawnEntry {
+            score: Tapered::new((score >> 32) as u32 as i32, score as u32 as i32),
+            passed: BitBoard(passed),
+        })
+    }
+
+    fn put(&self, hash: u64, entry: PawnEntry) {
+        let slot = &self.slots[hash as usize & MASK];
+        let score = ((entry.score.mg as u32 as u64) << 32) | entry.score.eg as u32 as u64;
+        slot.key
+            .store(hash ^ score ^ entry.passed.0, Ordering::Relaxed);
+        slot.score.store(score, Ordering::Relaxed);
+        slot.passed.store(entry.passed.0, Ordering::Relaxed);
+    }
+}
+
+/// A hash of where the pawns are, the same for every position with the same pawns.
+/// The chess crate's `get_pawn_hash` is not implemented.
+fn pawn_hash(board: &Board) -> u64 {
+    let pawns = board.pieces(Piece::Pawn);
+    let white = (pawns & board.color_combined(Color::White)).0;
+    let black = (pawns & board.color_combined(Color::Black)).0;
+    mix(white) ^ mix(black ^ 0x9e37_79b9_7f4a_7c15).rotate_left(32)
+}
+
+/// The splitmix64 finalizer, spreading every input bit over the whole hash.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The pawn structure score for white, taking what only depends on the pawns from `table`.
+pub(super) fn eval_pawns(board: &Board, table: Option<&PawnTable>) -> Tapered {
+    let entry = match table {
+        Some(table) => {
+            let hash = pawn_hash(board);
+            match table.get(hash) {
+                Some(entry) => {
+                    Stat::PawnTableHits.inc();
+                    entry
+                }
+                None => {
+                    Stat::PawnTableMisses.inc();
+                    let entry = pawn_structure(board);
+                    table.put(hash, entry);
+                    entry
+                }
+            }
+        }
+        None => pawn_structure(board),
+    };
+    entry.score + blocked_passers(board, entry.passed) + shield(board, Color::White)
+        - shield(board, Color::Black)
+}
+
+fn pawn_structure(board: &Board) -> PawnEntry {
+    let white = structure(board, Color::White);
+    let black = structure(board, Color::Black);
+    PawnEntry {
+        score: white.score - black.score,
+        passed: white.passed | black.passed,
+    }
+}
+
+/// Doubled, isolated, backward, connected and passed pawns of `color`.
+fn structure(board: &Board, color: Color) -> PawnEntry {
+    let pawns = board.pieces(Piece::Pawn);
+    let own = pawns & board.color_combined(color);
+    let enemy = pawns & board.color_combined(!color);
+    let mut score = Tapered::default();
+    let mut passed = EMPTY;
+
+    for square in own {
+        let file = get_file(square.get_file());
+        let neighbours = get_adjacent_files(square.get_file());
+        let ahead = ahead_of(square, color);
+        let rank = relative_rank(square, color);
+
+        if (own & file & ahead).popcnt() > 0 {
+            // Only the rearmost pawn of a file is doubled, so each extra pawn counts once
+            score += DOUBLED;
+        }
+        if own & neighbours == EMPTY {
+            score += ISOLATED;
+        } else if own & neighbours & !ahead == EMPTY {
+            // Cannot be defended by a pawn, and cannot advance safely either
+            let stop = forward(square, color);
+            if stop.is_some_and(|stop| get_pawn_attacks(stop, color, enemy) != EMPTY) {
+                score += BACKWARD;
+            }
+        }
+
+        let defended = get_pawn_attacks(square, !color, own) != EMPTY;
+        let phalanx = own & neighbours & BitBoard::from_square(square).rank_mask() != EMPTY;
+        if defended || phalanx {
+            score += CONNECTED + CONNECTED_RANK * rank as i32;
+        }
+
+        // The rear one of doubled pawns is not passed
+        if (pawns & file | enemy & neighbours) & ahead == EMPTY {
+            score += PASSED[rank];
+            passed |= BitBoard::from_square(square);
+        }
+    }
+    PawnEntry { score, passed }
+}
+
+/// A passed pawn is worth half as much while a piece stands right in front of it.
+fn blocked_passers(board: &Board, passed: BitBoard) -> Tapered {
+    let mut score = Tapered::default();
+    for square in passed {
+        let color = board.color_on(square).unwrap();
+        let blocked = forward(square, color).is_some_and(|stop| board.piece_on(stop).is_some());
+        if blocked {
+            let bonus = PASSED[relative_rank(square, color)];
+            let half = Tapered::new(bonus.mg / 2, bonus.eg / 2);
+            score += match color {
+                Color::White => -half,
+                Color::Black => half,
+            };
+        }
+    }
+    score
+}
+
+/// Own pawns right in front of the king of `color`.
+fn shield(board: &Board, color: Color) -> Tapered {
+    let king = board.king_square(color);
+    let own = board.pieces(Piece::Pawn) & board.color_combined(color);
+    let files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+    let mut score = Tapered::default();
+    let mut rank = forward(king, color);
+    for bonus in SHIELD {
+        match rank {
+            Some(square) => {
+                let count = (own & files & BitBoard::from_square(square).rank_mask()).popcnt();
+                score += bonus * count as i32;
+                rank = forward(square, color);
+            }
+            None => break,
+        }
+    }
+    score
+}
+
+/// All squares on ranks in front of `square`, from `color`'s point of view.
+pub(super) fn ahead_of(square: Square, color: Color) -> BitBoard {
+    let rank = square.get_rank().to_index() as u32;
+    match color {
+        Color::White => BitBoard(u64::MAX.checked_shl(8 * (rank + 1)).unwrap_or(0)),
+        Color::Black => BitBoard((1u64 << (8 * rank)) - 1),
+    }
+}
+
+/// The rank of `square` counting from 0 on `color`'s back rank.
+pub(super) fn relative_rank(square: Square, color: Color) -> usize {
+    match color {
+        Color::White => square.get_rank().to_index(),
+        Color::Black => 7 - square.get_rank().to_index(),
+    }
+}
+
+fn forward(square: Square, color: Color) -> Option<Square> {
+    match color {
+        Color::White => square.up(),
+        Color::Black => square.down(),
+    }
+}
+
+trait RankMask {
+    /// All squares on the ranks of the squares in this bitboard.
+    fn rank_mask(self) -> BitBoard;
+}
+
+impl RankMask for BitBoard {
+    fn rank_mask(self) -> BitBoard {
+        self.fold(EMPTY, |mask, square| {
+            mask | BitBoard(0xff << (8 * square.get_rank().to_index()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        eval_pawns, pawn_hash, pawn_structure, structure, PawnTable, BACKWARD, CONNECTED,
+        CONNECTED_RANK, DOUBLED, ISOLATED, PASSED,
+    };
+    use crate::ai::evaluation::Tapered;
+    use chess::{BitBoard, Board, Color, Square};
+    use std::str::FromStr;
+
+    fn white(fen: &str) -> Tapered {
+        structure(&Board::from_str(fen).unwrap(), Color::White).score
+    }
+
+    #[test]
+    fn test_structure() {
+        // A lone pawn is isolated and, with no enemy pawns, passed
+        assert_eq!(
+            white("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"),
+            ISOLATED + PASSED[1]
+        );
+        // Doubled and isolated, the front pawn is passed
+        assert_eq!(
+            white("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1"),
+            DOUBLED + ISOLATED + ISOLATED + PASSED[2]
+        );
+        // The c-pawn cannot be defended, and the d4 pawn keeps it from advancing;
+        // the b-pawn is defended by it, and passed
+        let board = Board::from_str("4k3/8/8/8/3p4/1P6/2P5/4K3 w - - 0 1").unwrap();
+        let entry = structure(&board, Color::White);
+        assert_eq!(entry.passed, BitBoard::from_square(Square::B3));
+        assert_eq!(
+            entry.score,
+            BACKWARD + CONNECTED + CONNECTED_RANK * 2 + PASSED[2]
+        );
+    }
+
+    #[test]
+    fn test_passed() {
+        let board = Board::from_str("4k3/8/1P6/8/8/8/p7/4K3 w - - 0 1").unwrap();
+        let entry = pawn_structure(&board);
+        assert_eq!(
+            entry.passed,
+            BitBoard::from_square(Square::B6) | BitBoard::from_square(Square::A2)
+        );
+        // The black pawn is further advanced
+        assert!(entry.score.eg < 0);
+        // A blocked passer is worth less
+        let free = eval_pawns(&board, None);
+        let blocked = Board::from_str("4k3/1n6/1P6/8/8/8/p7/4K3 w - - 0 1").unwrap();
+        assert!(eval_pawns(&blocked, None).eg < free.eg);
+    }
+
+    #[test]
+    fn test_pawn_table() {
+        let board =
+            Board::from_str("r1bqk2r/ppp2pp1/2n2n2/3Pp2p/2P5/P2P1N2/2P2PPP/R1BQKB1R b KQkq - 0 8")
+                .unwrap();
+        let table = PawnTable::new();
+        assert_eq!(table.get(pawn_hash(&board)), None);
+        let uncached = eval_pawns(&board, None);
+        assert_eq!(eval_pawns(&board, Some(&table)), uncached);
+        assert_eq!(table.get(pawn_hash(&board)), Some(pawn_structure(&board)));
+        assert_eq!(eval_pawns(&board, Some(&table)), uncached);
+
+        // Only pawns matter for the hash
+        let moved = board.make_move_new(chess::ChessMove::new(Square::C6, Square::D4, None));
+        assert_eq!(pawn_hash(&moved), pawn_hash(&board));
+        let pushed = board.make_move_new(chess::ChessMove::new(Square::A7, Square::A6, None));
+        assert_ne!(pawn_hash(&pushed), pawn_hash(&board));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::ai::mate::MAX_MOVES;
+use chess::{Board, BoardStatus, ChessMove, MoveGen};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// What a chess problem asks for, in moves of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stipulation {
+    /// `h#n`: The side to move and the other side cooperate,
+    /// so that the other side mates the side to move on its `n`th move.
+    Helpmate(u16),
+    /// `s#n`: The side to move forces the other side to mate it within `n` moves,
+    /// against any defence.
+    Selfmate(u16),
+}
+
+impl FromStr for Stipulation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, moves) = s.split_at(s.find('#').map_or(0, |i| i + 1));
+        let moves = moves
+            .parse()
+            .ok()
+            .filter(|m| (1..=MAX_MOVES).contains(m))
+            .ok_or_else(|| {
+                format!(
+                    "Invalid number of moves in {}, expected 1 to {}",
+                    s, MAX_MOVES
+                )
+            })?;
+        match kind {
+            "h#" => Ok(Stipulation::Helpmate(moves)),
+            "s#" => Ok(Stipulation::Selfmate(moves)),
+            _ => Err(format!("Unknown stipulation {}, expected h#n or s#n", s)),
+        }
+    }
+}
+
+impl fmt::Display for Stipulation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stipulation::Helpmate(moves) => write!(f, "h#{}", moves),
+            Stipulation::Selfmate(moves) => write!(f, "s#{}", moves),
+        }
+    }
+}
+
+/// Everything that fulfils a stipulation.
+#[derive(Debug, Default)]
+pub struct Solutions {
+    /// Every line of play; for selfmates, every defence and every working reply to it
+    pub lines: Vec<Vec<ChessMove>>,
+    /// Distinct first moves; more than one means the problem is cooked
+    pub keys: Vec<ChessMove>,
+    /// Lines after which the solving side has more than one way to go on
+    pub duals: Vec<Vec<ChessMove>>,
+}
+
+impl Solutions {
+    pub fn is_cooked(&self) -> bool {
+        self.keys.len() > 1
+    }
+}
+
+/// Solves helpmates and selfmates by trying every move, remembering which positions
+/// work with how many plies left so that only successful lines are enumerated.
+pub struct ProblemSolver {
+    /// Keyed by position and remaining plies
+    table: HashMap<(u64, u16), bool>,
+    nodes: u64,
+}
+
+impl ProblemSolver {
+    pub fn new() -> Self {
+        Self {
+            table: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// Positions examined so far.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    pub fn solve(&mut self, board: &Board, stipulation: Stipulation) -> Solutions {
+        self.table.clear();
+        let mut solutions = Solutions::default();
+        let mut line = Vec::new();
+        match stipulation {
+            Stipulation::Helpmate(moves) => {
+                self.helpmate_lines(board, 2 * moves, &mut line, &mut solutions)
+            }
+            Stipulation::Selfmate(moves) => {
+                self.selfmate_lines(board, 2 * moves - 1, &mut line, &mut solutions)
+            }
+        }
+        for line in &solutions.lines {
+            if !solutions.keys.contains(&line[0]) {
+                solutions.keys.push(line[0]);
+            }
+        }
+        solutions
+    }
+
+    /// If the side to move can be mated after exactly `plies` plies, with both sides helping.
+    fn helpmate(&mut self, board: &Board, plies: u16) -> bool {
+        self.nodes += 1;
+        if plies == 0 {
+            return board.status() == BoardStatus::Checkmate;
+        }
+        if let Some(result) = self.table.get(&(board.get_hash(), plies)) {
+            return *result;
+        }
+        let result = MoveGen::new_legal(board).any(|m| {
+            let after = board.make_move_new(m);
+            // The mating move has to give check at least
+            (plies > 1 || after.checkers().popcnt() > 0) && self.helpmate(&after, plies - 1)
+        });
+        self.table.insert((board.get_hash(), plies), result);
+        result
+    }
+
+    fn helpmate_lines(
+        &mut self,
+        board: &Board,
+        plies: u16,
+        line: &mut Vec<ChessMove>,
+        solutions: &mut Solutions,
+    ) {
+        if plies == 0 {
+            solutions.lines.push(line.clone());
+            return;
+        }
+        let working = MoveGen::new_legal(board)
+            .filter(|m| self.helpmate(&board.make_move_new(*m), plies - 1))
+            .collect::<Vec<_>>();
+        // Both sides are solving; alternatives after the first move are duals
+        if working.len() > 1 && !line.is_empty() {
+            solutions.duals.push(line.clone());
+        }
+        for mov in working {
+            line.push(mov);
+            self.helpmate_lines(&board.make_move_new(mov), plies - 1, line, solutions);
+            line.pop();
+        }
+    }
+
+    /// If the side to move on `board` forces the other side to mate it
+    /// within `plies` plies, counting from its own move.
+    fn selfmate(&mut self, board: &Board, plies: u16) -> bool {
+        self.nodes += 1;
+        if let Some(result) = self.table.get(&(board.get_hash(), plies)) {
+            return *result;
+        }
+        let result = MoveGen::new_legal(board).any(|m| self.forced(&board.make_move_new(m), plies));
+        self.table.insert((board.get_hash(), plies), result);
+        result
+    }
+
+    /// If the side to move on `board`, after the solver's move with `plies` plies left
+    /// before it, has to mate the solver whatever it does.
+    fn forced(&mut self, board: &Board, plies: u16) -> bool {
+        let mut defences = MoveGen::new_legal(board).peekable();
+        // Mated or stalemated, so it cannot mate any more
+        defences.peek().is_some()
+            && defences.all(|d| {
+                let after = board.make_move_new(d);
+                after.status() == BoardStatus::Checkmate
+                    || (plies > 2 && self.selfmate(&after, plies - 2))
+            })
+    }
+
+    fn selfmate_lines(
+        &mut self,
+        board: &Board,
+        plies: u16,
+        line: &mut Vec<ChessMove>,
+        solutions: &mut Solutions,
+    ) {
+        let working = MoveGen::new_legal(board)
+            .filter(|m| self.forced(&board.make_move_new(*m), plies))
+            .collect::<Vec<_>>();
+        // Only the solving side's alternatives count; the defence may choose freely
+        if working.len() > 1 && !line.is_empty() {
+            solutions.duals.push(line.clone());
+        }
+        for mov in working {
+            let after = board.make_move_new(mov);
+            line.push(mov);
+            for defence in MoveGen::new_legal(&after) {
+                let after_defence = after.make_move_new(defence);
+                line.push(defence);
+                if after_defence.status() == BoardStatus::Checkmate {
+                    solutions.lines.push(line.clone());
+                } else {
+                    self.selfmate_lines(&after_defence, plies - 2, line, solutions);
+                }
+                line.pop();
+            }
+            line.pop();
+        }
+    }
+}
+
+impl Default for ProblemSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProblemSolver, Stipulation};
+    use chess::{Board, BoardStatus, ChessMove, Square};
+    use std::str::FromStr;
+
+    fn play(board: &Board, line: &[ChessMove]) -> Board {
+        line.iter().fold(*board, |board, mov| {
+            assert!(board.legal(*mov));
+            board.make_move_new(*mov)
+        })
+    }
+
+    #[test]
+    fn test_parse_stipulation() {
+        assert_eq!("h#2".parse(), Ok(Stipulation::Helpmate(2)));
+        assert_eq!("s#3".parse(), Ok(Stipulation::Selfmate(3)));
+        assert!("#2".parse::<Stipulation>().is_err());
+        assert!("h#0".parse::<Stipulation>().is_err());
+        // The plies have to fit in a u16
+        assert_eq!("s#32767".parse(), Ok(Stipulation::Selfmate(32767)));
+        assert!("h#40000".parse::<Stipulation>().is_err());
+        assert!("h#x".parse::<Stipulation>().is_err());
+    }
+
+    #[test]
+    fn test_helpmate() {
+        // 1. Ka8 Rh8#, the only way for the black king to get mated
+        let board = Board::from_str("1k6/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+        let solutions = ProblemSolver::new().solve(&board, Stipulation::Helpmate(1));
+        assert_eq!(
+            solutions.lines,
+            vec![vec![
+                ChessMove::new(Square::B8, Square::A8, None),
+                ChessMove::new(Square::H1, Square::H8, None),
+            ]]
+        );
+        assert!(!solutions.is_cooked());
+        assert!(solutions.duals.is_empty());
+    }
+
+    #[test]
+    fn test_cooks_and_duals() {
+        // With a move more, both sides have many ways to get there
+        let board = Board::from_str("1k6/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+        let solutions = ProblemSolver::new().solve(&board, Stipulation::Helpmate(2));
+        assert!(solutions.is_cooked());
+        assert!(!solutions.duals.is_empty());
+        for line in &solutions.lines {
+            assert_eq!(line.len(), 4);
+            assert_eq!(play(&board, line).status(), BoardStatus::Checkmate);
+        }
+        // Waiting with the rook after 1. Ka8 is a dual
+        let ka8 = ChessMove::new(Square::B8, Square::A8, None);
+        assert!(solutions.duals.contains(&vec![ka8]));
+    }
+
+    #[test]
+    fn test_selfmate() {
+        // 1. Qg2+ leaves black nothing but Bxg2#
+        let board = Board::from_str("8/1Q6/8/8/7p/7k/7p/5b1K w - - 0 1").unwrap();
+        let solutions = ProblemSolver::new().solve(&board, Stipulation::Selfmate(1));
+        assert_eq!(
+            solutions.keys,
+            vec![ChessMove::new(Square::B7, Square::G2, None)]
+        );
+        assert!(!solutions.is_cooked());
+        for line in &solutions.lines {
+            let end = play(&board, line);
+            assert_eq!(end.status(), BoardStatus::Checkmate);
+            assert_eq!(end.side_to_move(), board.side_to_move());
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::ai::ordering::Heuristics;
+use crate::ai::settings::Settings;
+use crate::ai::table::{Bound, TransTable};
+use crate::ai::tree::SearchTree;
+use chess::{Board, ChessMove};
+use std::sync::Arc;
+
+/// A search algorithm. Implementations can be swapped with [`crate::ai::Engine::set_searcher`],
+/// e.g. to compare them against each other.
+pub trait Searcher {
+    fn name(&self) -> &'static str;
+
+    /// Find the best move on `board` within `limits`, passing progress to `info` on the way.
+    /// `history` holds the positions played before `board` since the last irreversible move.
+    fn search(
+        &mut self,
+        state: &SharedState,
+        board: Board,
+        history: &[u64],
+        settings: Settings,
+        limits: Limits,
+        info: &mut dyn FnMut(&Info),
+    ) -> Report;
+}
+
+/// What the engine keeps between searches, for any searcher to use.
+#[derive(Clone)]
+pub struct SharedState {
+    pub table: Arc<TransTable>,
+    pub heuristics: Arc<Heuristics>,
+    /// Records the search tree, for debugging
+    pub tree: Option<Arc<SearchTree>>,
+}
+
+/// When a search has to stop, and what it searches.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Time per move in seconds; may be stretched by time management
+    pub time: f32,
+    /// Search exactly this many nodes instead of for a time, reproducibly
+    pub nodes: Option<u64>,
+    /// Only search these root moves; all of them if empty
+    pub search_moves: Vec<ChessMove>,
+}
+
+impl Limits {
+    /// If `mov` is one of the root moves to search.
+    pub fn allows(&self, mov: ChessMove) -> bool {
+        self.search_moves.is_empty() || self.search_moves.contains(&mov)
+    }
+}
+
+/// Progress of a search.
+#[derive(Clone, Debug)]
+pub enum Info {
+    /// A move proven better than the previous best move during a depth,
+    /// with the score it was proven with
+    BestMove {
+        depth: i16,
+        mov: ChessMove,
+        score: i32,
+    },
+    /// A root move is about to be searched, the `number`th at this depth counting from 1
+    CurrentMove {
+        depth: i16,
+        mov: ChessMove,
+        number: usize,
+    },
+    /// A root move was searched
+    RootMove(RootMove),
+    /// The best move and its score after a complete depth
+    Depth {
+        depth: i16,
+        best: ChessMove,
+        score: i32,
+    },
+}
+
+/// How a root move was evaluated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootMove {
+    pub mov: ChessMove,
+    pub score: i32,
+    /// How `score` relates to the real score of the move
+    pub bound: Bound,
+    /// Nodes spent on the move at `depth`
+    pub nodes: u64,
+    pub depth: i16,
+    /// The expected line of play, starting with `mov`
+    pub pv: Vec<ChessMove>,
+}
+
+/// The outcome of a search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub best: ChessMove,
+    /// Score of the best move at `depth`
+    pub score: i32,
+    /// The depth the best move was found at, which may not have been completed
+    /// for the other moves; 0 if the move was played without a search
+    pub depth: i16,
+    pub nodes: u64,
+    /// The expected line of play, starting with the best move
+    pub pv: Vec<ChessMove>,
+    /// How often each root move was visited, for searchers that count visits
+    pub visits: Vec<(ChessMove, u32)>,
+    /// The latest result of each root move, deepest and best first,
+    /// for searchers that search the root moves one by one
+    pub root_moves: Vec<RootMove>,
+}
+
+impl Report {
+    /// A move played without searching.
+    pub fn unsearched(best: ChessMove) -> Self {
+        Self {
+            best,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: vec![best],
+            visits: Vec::new(),
+            root_moves: Vec::new(),
+        }
+    }
+
+    /// Take the best move, score, depth and root move results from a progress update.
+    pub fn update(&mut self, info: &Info) {
+        match *info {
+            Info::BestMove { depth, mov, score }
+            | Info::Depth {
+                depth,
+                best: mov,
+                score,
+            } => {
+                self.best = mov;
+                self.score = score;
+                self.depth = depth;
+            }
+            Info::CurrentMove { .. } => (),
+            Info::RootMove(ref root_move) => {
+                self.root_moves.retain(|r| r.mov != root_move.mov);
+                self.root_moves.push(root_move.clone());
+                self.root_moves.sort_by_key(|r| (-r.depth, -r.score));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Info, Report};
+    use chess::{ChessMove, Square};
+
+    #[test]
+    fn test_update() {
+        let first = ChessMove::new(Square::E2, Square::E4, None);
+        let better = ChessMove::new(Square::D2, Square::D4, None);
+        let mut report = Report::unsearched(first);
+        report.update(&Info::Depth {
+            depth: 3,
+            best: first,
+            score: 10,
+        });
+        // A better move during depth 4 comes with the score it was proven with at depth 4
+        report.update(&Info::BestMove {
+            depth: 4,
+            mov: better,
+            score: 30,
+        });
+        assert_eq!((report.best, report.score, report.depth), (better, 30, 4));
+        report.update(&Info::Depth {
+            depth: 4,
+            best: better,
+            score: 30,
+        });
+        assert_eq!((report.best, report.score, report.depth), (better, 30, 4));
+    }
+}
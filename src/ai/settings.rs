@@ -0,0 +1,89 @@
+use crate::ai::draw::Contempt;
+
+/// Tunable behaviour of a search.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub contempt: Contempt,
+    /// Prune zero window nodes where a shallow search of good captures beats beta by a margin;
+    /// off by default, as it has not been shown to make play stronger
+    pub probcut: Option<ProbCut>,
+    /// Prune nodes where several moves beat beta in a reduced search
+    pub multi_cut: Option<MultiCut>,
+    /// Answer without searching when the move is obvious, whatever the searcher; off for analysis
+    pub shortcuts: bool,
+    /// Search a number of nodes on a single thread instead of for a time,
+    /// so the same input always gives the same result
+    pub deterministic: Option<Deterministic>,
+    pub evaluator: Evaluator,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProbCut {
+    /// Minimum remaining depth to try ProbCut at
+    pub depth: i16,
+    /// How much the shallow search has to beat beta by
+    pub margin: i32,
+    /// How much shallower the shallow search is
+    pub reduction: i16,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MultiCut {
+    /// Minimum remaining depth to try multi-cut at
+    pub depth: i16,
+    /// How many moves to try
+    pub moves: usize,
+    /// How many of them need to beat beta for the node to be pruned
+    pub cutoffs: usize,
+    /// How much shallower the moves are searched
+    pub reduction: i16,
+}
+
+/// How positions are scored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Evaluator {
+    /// The NNUE network from `model.nnue`, or the classical evaluation if it could not be loaded
+    Nnue,
+    /// Material, piece-square tables and positional terms, tapered by game phase
+    Classical,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Deterministic {
+    /// Nodes to search per move
+    pub nodes: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            contempt: Contempt::default(),
+            probcut: None,
+            multi_cut: None,
+            shortcuts: true,
+            deterministic: None,
+            evaluator: Evaluator::Nnue,
+        }
+    }
+}
+
+impl Default for ProbCut {
+    fn default() -> Self {
+        Self {
+            depth: 5,
+            margin: 100,
+            reduction: 4,
+        }
+    }
+}
+
+impl Default for MultiCut {
+    fn default() -> Self {
+        Self {
+            depth: 5,
+            moves: 6,
+            cutoffs: 3,
+            reduction: 3,
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering;
 
-const LEN: usize = 9;
+const LEN: usize = 17;
+#[allow(clippy::declare_interior_mutable_const)]
 const EMPTY: AtomicU32 = AtomicU32::new(0);
 static STATS: [AtomicU32; LEN] = [EMPTY; LEN];
 static STATS_LAST_DEPTH: [AtomicU32; LEN] = [EMPTY; LEN];
@@ -18,6 +19,14 @@ pub enum Stat {
     TableEvalHits = 6,
     NNUECacheHits = 7,
     NNUECacheMisses = 8,
+    TableStores = 9,
+    TableOverwrites = 10,
+    TableCollisions = 11,
+    IIDSearches = 12,
+    ProbCutPrunes = 13,
+    MultiCutPrunes = 14,
+    PawnTableHits = 15,
+    PawnTableMisses = 16,
 }
 
 impl Stat {
@@ -54,10 +63,38 @@ impl Stat {
             "   Transposition table misses during move evaluation: {}",
             stat[2].load(Ordering::Relaxed)
         );
+        log::debug!(
+            "   Transposition table stores: {} ({:.1}% overwrites, {:.1}% collisions)",
+            stat[9].load(Ordering::Relaxed),
+            Self::percent(stat, 10, 9),
+            Self::percent(stat, 11, 9)
+        );
         log::debug!("   NNUE cache hits: {}", stat[7].load(Ordering::Relaxed));
         log::debug!("   NNUE cache misses: {}", stat[8].load(Ordering::Relaxed));
+        log::debug!("   Pawn table hits: {}", stat[15].load(Ordering::Relaxed));
+        log::debug!("   Pawn table misses: {}", stat[16].load(Ordering::Relaxed));
         log::debug!("   Checkmates found: {}", stat[3].load(Ordering::Relaxed));
         log::debug!("   Branches pruned: {}", stat[4].load(Ordering::Relaxed));
         log::debug!("   Incorrect PV moves: {}", stat[5].load(Ordering::Relaxed));
+        log::debug!(
+            "   Internal iterative deepening searches: {}",
+            stat[12].load(Ordering::Relaxed)
+        );
+        log::debug!(
+            "   Nodes pruned by ProbCut: {}",
+            stat[13].load(Ordering::Relaxed)
+        );
+        log::debug!(
+            "   Nodes pruned by multi-cut: {}",
+            stat[14].load(Ordering::Relaxed)
+        );
+    }
+
+    fn percent(stat: &[AtomicU32], part: usize, whole: usize) -> f32 {
+        let whole = stat[whole].load(Ordering::Relaxed);
+        if whole == 0 {
+            return 0.0;
+        }
+        stat[part].load(Ordering::Relaxed) as f32 * 100.0 / whole as f32
     }
 }
@@ -1,77 +1,633 @@
+use crate::ai::pawns::PawnTable;
+use crate::ai::statistics::Stat;
+use chess::{ChessMove, ALL_PIECES, ALL_SQUARES};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
 const CAPACITY: usize = usize::pow(2, 18);
 const MASK: usize = CAPACITY - 1;
+const BUCKET_SIZE: usize = 4;
+const BUCKET_MASK: usize = CAPACITY / BUCKET_SIZE - 1;
+/// How many plies of depth an entry is worth less per search it has aged.
+const AGE_PENALTY: i32 = 8;
+/// Generations are stored in 6 bits and wrap around.
+const GENERATION_MASK: u8 = 63;
+
+const FILE_MAGIC: &[u8; 8] = b"CHEVIITT";
+/// Needs to be bumped whenever the packed entry layout changes.
+const FILE_VERSION: u32 = 3;
 
 /// Transmutation table.
-/// Can be used multi-threaded; however it does not handle hash collisions or proper synchronization.
-/// This is "safe".
+/// Can be used multi-threaded without any locking: every slot is a pair of atomic words,
+/// where the key is stored XORed with the data. A torn write (key and data from
+/// different stores) will fail verification on read and is treated as a miss.
+/// This does not prevent hash collisions on the full 64 bits, but is free of data races.
+///
+/// Search entries are kept in buckets of 4 slots, one cache line each.
+/// When a bucket is full, the shallowest and oldest entry is replaced.
 pub struct TransTable {
-    entries: Vec<Option<Entry>>,
-    entries_nnue: Vec<Option<NNUEEntry>>,
+    entries: Vec<Bucket>,
+    entries_nnue: Vec<Slot>,
+    pawns: PawnTable,
+    generation: AtomicU8,
 }
 
 impl TransTable {
-    pub fn get(&self, zobrist: u64) -> &Option<Entry> {
-        let index = zobrist as usize & MASK;
-        unsafe {
-            let val = self.entries.get_unchecked(index);
-            match val {
-                Some(entry) if entry.zobrist == zobrist => val,
-                _ => &None,
-            }
-        }
+    pub fn get(&self, zobrist: u64) -> Option<Entry> {
+        let bucket = unsafe { self.entries.get_unchecked(zobrist as usize & BUCKET_MASK) };
+        bucket
+            .slots
+            .iter()
+            .find_map(|slot| slot.load(zobrist))
+            .map(|data| Entry::unpack(zobrist, data))
     }
 
-    pub fn get_nnue(&self, zobrist: u64) -> &Option<NNUEEntry> {
+    pub fn get_nnue(&self, zobrist: u64) -> Option<NNUEEntry> {
         let index = zobrist as usize & MASK;
-        unsafe {
-            let val = self.entries_nnue.get_unchecked(index);
-            match val {
-                Some(entry) if entry.zobrist == zobrist => val,
-                _ => &None,
-            }
-        }
+        unsafe { self.entries_nnue.get_unchecked(index) }
+            .load(zobrist)
+            .map(|data| NNUEEntry {
+                zobrist,
+                score: data as u32 as i32,
+            })
     }
 
     pub fn put(&self, entry: Entry) {
-        let index = entry.zobrist as usize & MASK;
-        unsafe {
-            // Messing up safety one cast at a time!
-            let vec = &self.entries as *const Vec<Option<Entry>>;
-            let vecp = vec as *mut Vec<Option<Entry>>;
-            let vecmut = &mut *vecp;
-            vecmut[index] = Some(entry);
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.insert(entry.zobrist, entry.pack(generation), false);
+    }
+
+    /// Insert packed entry data into its bucket.
+    /// If the position is already stored, it is replaced unless `keep_deeper` is set
+    /// and the stored entry has a higher depth of score.
+    fn insert(&self, zobrist: u64, data: u64, keep_deeper: bool) {
+        let generation = Entry::generation(data);
+        let bucket = unsafe { self.entries.get_unchecked(zobrist as usize & BUCKET_MASK) };
+        Stat::TableStores.inc();
+
+        // Same position already stored: Refresh it
+        if let Some((slot, old)) = bucket
+            .slots
+            .iter()
+            .find_map(|s| s.load(zobrist).map(|old| (s, old)))
+        {
+            if keep_deeper && Entry::depth_of_score(old) > Entry::depth_of_score(data) {
+                return;
+            }
+            Stat::TableOverwrites.inc();
+            slot.store(zobrist, data);
+            return;
+        }
+
+        // Otherwise, evict the least valuable entry
+        let (victim, occupied) = bucket
+            .slots
+            .iter()
+            .map(|slot| (slot, slot.worth(generation)))
+            .min_by_key(|(_, worth)| worth.unwrap_or(i32::MIN))
+            .map(|(slot, worth)| (slot, worth.is_some()))
+            .unwrap();
+        if occupied {
+            Stat::TableCollisions.inc();
         }
+        victim.store(zobrist, data);
     }
 
     pub fn put_nnue(&self, entry: NNUEEntry) {
         let index = entry.zobrist as usize & MASK;
-        unsafe {
-            // Messing up safety one cast at a time!
-            let vec = &self.entries_nnue as *const Vec<Option<NNUEEntry>>;
-            let vecp = vec as *mut Vec<Option<NNUEEntry>>;
-            let vecmut = &mut *vecp;
-            vecmut[index] = Some(entry);
+        unsafe { self.entries_nnue.get_unchecked(index) }
+            .store(entry.zobrist, entry.score as u32 as u64);
+    }
+
+    /// The pawn structure cache, which lives as long as the rest of the table.
+    pub fn pawns(&self) -> &PawnTable {
+        &self.pawns
+    }
+
+    /// Start a new search; entries of previous searches become
+    /// preferred for replacement over ones of the current search.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Write all entries to a file, to be loaded or merged in a later session.
+    /// Must not be called while a search is using the table.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = ChecksumWriter::new(BufWriter::new(File::create(path)?));
+        out.write_all(FILE_MAGIC)?;
+        out.write_all(&FILE_VERSION.to_le_bytes())?;
+
+        let entries = self.entries.iter().flat_map(|b| b.slots.iter());
+        for slots in [
+            entries.collect::<Vec<_>>(),
+            self.entries_nnue.iter().collect(),
+        ] {
+            let used = slots.iter().filter_map(|s| s.raw()).collect::<Vec<_>>();
+            out.write_all(&(used.len() as u64).to_le_bytes())?;
+            for (zobrist, data) in used {
+                out.write_all(&zobrist.to_le_bytes())?;
+                out.write_all(&data.to_le_bytes())?;
+            }
         }
+
+        let checksum = out.checksum;
+        out.write_all(&checksum.to_le_bytes())?;
+        out.flush()
+    }
+
+    /// Load a table previously written with [`TransTable::save`].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let table = Self::new();
+        table.merge(path)?;
+        Ok(table)
+    }
+
+    /// Add all entries of a saved table into this one.
+    /// For positions present in both, the deeper result is kept.
+    /// The file is fully verified before anything is inserted.
+    pub fn merge(&self, path: &Path) -> io::Result<()> {
+        let mut input = ChecksumReader::new(BufReader::new(File::open(path)?));
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != FILE_MAGIC {
+            return Err(invalid_data("not a chevii table file"));
+        }
+        let version = input.read_u32()?;
+        if version != FILE_VERSION {
+            return Err(invalid_data(&format!(
+                "table file has version {}, expected {}",
+                version, FILE_VERSION
+            )));
+        }
+
+        let entries = input.read_pairs()?;
+        let entries_nnue = input.read_pairs()?;
+        let checksum = input.checksum;
+        if input.read_u64()? != checksum {
+            return Err(invalid_data("table file checksum mismatch"));
+        }
+
+        // Loaded entries count as part of the current search
+        let generation = self.generation.load(Ordering::Relaxed);
+        for (zobrist, data) in entries {
+            self.insert(zobrist, Entry::with_generation(data, generation), true);
+        }
+        for (zobrist, data) in entries_nnue {
+            unsafe { self.entries_nnue.get_unchecked(zobrist as usize & MASK) }
+                .store(zobrist, data);
+        }
+        Ok(())
     }
 
     pub fn new() -> Self {
         Self {
-            entries: vec![None; CAPACITY],
-            entries_nnue: vec![None; CAPACITY],
+            entries: (0..CAPACITY / BUCKET_SIZE)
+                .map(|_| Bucket::default())
+                .collect(),
+            entries_nnue: (0..CAPACITY).map(|_| Slot::default()).collect(),
+            pawns: PawnTable::new(),
+            generation: AtomicU8::new(0),
         }
     }
 }
 
-#[derive(Clone)]
+impl Default for TransTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Bucket {
+    slots: [Slot; BUCKET_SIZE],
+}
+
+/// A single table slot; `key` holds `zobrist ^ data`.
+/// Relaxed ordering is enough, since a reader only ever trusts
+/// a pair that verifies against the zobrist it is looking for.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self, zobrist: u64) -> Option<u64> {
+        let key = self.key.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+        // An empty slot would otherwise verify for zobrist 0
+        if key ^ data == zobrist && (key, data) != (0, 0) {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    fn store(&self, zobrist: u64, data: u64) {
+        self.key.store(zobrist ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    /// The zobrist and data stored in this slot, if any.
+    fn raw(&self) -> Option<(u64, u64)> {
+        let key = self.key.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+        if key == 0 && data == 0 {
+            None
+        } else {
+            Some((key ^ data, data))
+        }
+    }
+
+    /// How valuable the entry in this slot is to keep, or `None` if the slot is empty.
+    fn worth(&self, generation: u8) -> Option<i32> {
+        let (_, data) = self.raw()?;
+        let age = (generation.wrapping_sub(Entry::generation(data)) & GENERATION_MASK) as i32;
+        Some(Entry::depth_of_score(data) as i32 - age * AGE_PENALTY)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// FNV-1a, continued over `bytes`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    checksum: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            checksum: FNV_OFFSET,
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.checksum = fnv1a(self.checksum, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ChecksumReader<R: Read> {
+    inner: R,
+    checksum: u64,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            checksum: FNV_OFFSET,
+        }
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    /// Read a length-prefixed list of (zobrist, data) pairs.
+    fn read_pairs(&mut self) -> io::Result<Vec<(u64, u64)>> {
+        let len = self.read_u64()?;
+        if len > CAPACITY as u64 {
+            return Err(invalid_data("table file has too many entries"));
+        }
+        (0..len)
+            .map(|_| Ok((self.read_u64()?, self.read_u64()?)))
+            .collect()
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.checksum = fnv1a(self.checksum, &buf[..read]);
+        Ok(read)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Entry {
     pub zobrist: u64,
     pub score: i32,
     pub depth_of_score: i16,
     pub depth_of_search: i16,
+    /// The best (or refuting) move found in this position, if any
+    pub best_move: Option<ChessMove>,
+    pub bound: Bound,
+}
+
+/// How a stored score relates to the real score of the position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact = 0,
+    /// The real score is at least as high (the search failed high)
+    Lower = 1,
+    /// The real score is at most as high (the search failed low)
+    Upper = 2,
+}
+
+impl Bound {
+    /// If a stored `score` with this bound decides the result of an `alpha`/`beta` search.
+    pub fn cuts(self, score: i32, alpha: i32, beta: i32) -> bool {
+        match self {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+        }
+    }
+}
+
+/// Largest score that can be stored; the packed score is 24 bits wide.
+const SCORE_LIMIT: i32 = (1 << 23) - 1;
+
+/// Packed layout: best move (16 bits), score (24), depth of score (8),
+/// depth of search (8), bound (2), generation (6).
+impl Entry {
+    fn pack(&self, generation: u8) -> u64 {
+        let score = self.score.clamp(-SCORE_LIMIT, SCORE_LIMIT) as u32 & 0xFF_FFFF;
+        (pack_move(self.best_move) as u64)
+            | ((score as u64) << 16)
+            | (pack_depth(self.depth_of_score) << 40)
+            | (pack_depth(self.depth_of_search) << 48)
+            | ((self.bound as u64) << 56)
+            | (((generation & GENERATION_MASK) as u64) << 58)
+    }
+
+    fn unpack(zobrist: u64, data: u64) -> Self {
+        Self {
+            zobrist,
+            score: (((data >> 16) as u32) << 8) as i32 >> 8,
+            depth_of_score: Self::depth_of_score(data),
+            depth_of_search: (data >> 48) as u8 as i8 as i16,
+            best_move: unpack_move(data as u16),
+            bound: match (data >> 56) & 3 {
+                1 => Bound::Lower,
+                2 => Bound::Upper,
+                _ => Bound::Exact,
+            },
+        }
+    }
+
+    fn depth_of_score(data: u64) -> i16 {
+        (data >> 40) as u8 as i8 as i16
+    }
+
+    fn generation(data: u64) -> u8 {
+        (data >> 58) as u8
+    }
+
+    fn with_generation(data: u64, generation: u8) -> u64 {
+        (data & !(0x3F << 58)) | (((generation & GENERATION_MASK) as u64) << 58)
+    }
 }
 
-#[derive(Clone)]
+/// Depths are stored in 8 bits; ones that do not fit are stored as the nearest one that does.
+fn pack_depth(depth: i16) -> u64 {
+    depth.clamp(i8::MIN as i16, i8::MAX as i16) as i8 as u8 as u64
+}
+
+/// Source (6 bits), destination (6) and promotion piece (3, 0 for none).
+/// A move from and to A1 is never legal, so 0 means no move.
+pub(super) fn pack_move(mov: Option<ChessMove>) -> u16 {
+    mov.map_or(0, |mov| {
+        (mov.get_source().to_index() as u16)
+            | ((mov.get_dest().to_index() as u16) << 6)
+            | (mov.get_promotion().map_or(0, |p| p.to_index() as u16 + 1) << 12)
+    })
+}
+
+pub(super) fn unpack_move(bits: u16) -> Option<ChessMove> {
+    if bits == 0 {
+        return None;
+    }
+    let promotion = (bits >> 12) as usize;
+    Some(ChessMove::new(
+        ALL_SQUARES[(bits & 63) as usize],
+        ALL_SQUARES[((bits >> 6) & 63) as usize],
+        if promotion == 0 {
+            None
+        } else {
+            Some(ALL_PIECES[promotion - 1])
+        },
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct NNUEEntry {
     pub zobrist: u64,
     pub score: i32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Bound, Entry, NNUEEntry, TransTable};
+    use chess::{ChessMove, Piece, Square};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::thread;
+    use test::Bencher;
+
+    #[test]
+    fn test_roundtrip() {
+        let table = TransTable::new();
+        let entry = Entry {
+            zobrist: 0xDEAD_BEEF_1234_5678,
+            score: -123456,
+            depth_of_score: 7,
+            depth_of_search: -3,
+            best_move: Some(ChessMove::new(Square::G7, Square::H8, Some(Piece::Knight))),
+            bound: Bound::Upper,
+        };
+        table.put(entry);
+        assert_eq!(table.get(entry.zobrist), Some(entry));
+        assert_eq!(table.get(entry.zobrist ^ (1 << 40)), None);
+        // Depths that do not fit are clamped instead of wrapping around
+        let deep = Entry {
+            depth_of_score: 300,
+            depth_of_search: -200,
+            ..entry
+        };
+        table.put(deep);
+        let stored = table.get(entry.zobrist).unwrap();
+        assert_eq!((stored.depth_of_score, stored.depth_of_search), (127, -128));
+        // Empty slots are not entries for zobrist 0
+        assert_eq!(table.get(0), None);
+        assert_eq!(table.get_nnue(0).map(|e| e.score), None);
+    }
+
+    #[test]
+    fn test_concurrent_writes_never_tear() {
+        // All threads hammer the same slot, each with a score derived from its zobrist.
+        // A torn read would show up as a verified entry with a mismatching score.
+        let table = Arc::new(TransTable::new());
+        let threads = (0..8u64)
+            .map(|t| {
+                let table = table.clone();
+                thread::spawn(move || {
+                    for i in 0..20000u64 {
+                        let zobrist = ((t * 20000 + i) << 18) | 42;
+                        table.put(Entry {
+                            zobrist,
+                            score: (zobrist >> 18) as i32,
+                            depth_of_score: (zobrist >> 18) as i8 as i16,
+                            depth_of_search: t as i16,
+                            best_move: None,
+                            bound: Bound::Exact,
+                        });
+                        let other = (((7 - t) * 20000 + i) << 18) | 42;
+                        if let Some(entry) = table.get(other) {
+                            assert_eq!(entry.score, (other >> 18) as i32);
+                            assert_eq!(entry.depth_of_score, (other >> 18) as i8 as i16);
+                            assert_eq!(entry.depth_of_search, (7 - t) as i16);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_replacement() {
+        let table = TransTable::new();
+        let entry = |zobrist: u64, depth: i16| Entry {
+            zobrist: (zobrist << 16) | 7,
+            score: 0,
+            depth_of_score: depth,
+            depth_of_search: depth,
+            best_move: None,
+            bound: Bound::Exact,
+        };
+
+        // Fill one bucket; the shallowest entry gets evicted first
+        for (i, depth) in [5, 2, 9, 6].iter().enumerate() {
+            table.put(entry(i as u64 + 1, *depth));
+        }
+        table.put(entry(5, 3));
+        assert!(table.get(entry(2, 2).zobrist).is_none());
+        assert!(table.get(entry(1, 5).zobrist).is_some());
+        assert!(table.get(entry(5, 3).zobrist).is_some());
+
+        // Entries of older searches are replaced before current ones, even if deeper
+        table.new_search();
+        table.put(entry(6, 4));
+        table.put(entry(7, 1));
+        assert!(table.get(entry(1, 5).zobrist).is_none());
+        assert!(table.get(entry(7, 1).zobrist).is_some());
+        assert!(table.get(entry(3, 9).zobrist).is_some());
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chevii-{}-{}.tt", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = temp_file("save-load");
+        let table = TransTable::new();
+        let entry = Entry {
+            zobrist: 0x1234_5678_9ABC_DEF0,
+            score: -42,
+            depth_of_score: 6,
+            depth_of_search: 8,
+            best_move: Some(ChessMove::new(Square::E2, Square::E4, None)),
+            bound: Bound::Lower,
+        };
+        table.put(entry);
+        table.put_nnue(NNUEEntry {
+            zobrist: 77,
+            score: 31,
+        });
+        table.save(&path).unwrap();
+
+        let loaded = TransTable::load(&path).unwrap();
+        assert_eq!(loaded.get(entry.zobrist), Some(entry));
+        assert_eq!(loaded.get_nnue(77).map(|e| e.score), Some(31));
+
+        // Any corruption is detected
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[20] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert!(TransTable::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_merge_keeps_deeper() {
+        let path = temp_file("merge");
+        let entry = |zobrist: u64, depth: i16| Entry {
+            zobrist,
+            score: depth as i32,
+            depth_of_score: depth,
+            depth_of_search: depth,
+            best_move: None,
+            bound: Bound::Exact,
+        };
+        let other = TransTable::new();
+        other.put(entry(1, 9));
+        other.put(entry(2, 3));
+        other.put(entry(3, 5));
+        other.save(&path).unwrap();
+
+        let table = TransTable::new();
+        table.put(entry(1, 4));
+        table.put(entry(2, 7));
+        table.merge(&path).unwrap();
+        assert_eq!(table.get(1), Some(entry(1, 9)));
+        assert_eq!(table.get(2), Some(entry(2, 7)));
+        assert_eq!(table.get(3), Some(entry(3, 5)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[bench]
+    fn bench_put_get(b: &mut Bencher) {
+        let table = TransTable::new();
+        let mut zobrist = 0x9E37_79B9_7F4A_7C15u64;
+        b.iter(|| {
+            zobrist = zobrist.wrapping_mul(6364136223846793005).wrapping_add(1);
+            table.put(Entry {
+                zobrist,
+                score: 10,
+                depth_of_score: 2,
+                depth_of_search: 4,
+                best_move: None,
+                bound: Bound::Exact,
+            });
+            table.get(zobrist)
+        });
+    }
+}
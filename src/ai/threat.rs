@@ -0,0 +1,127 @@
+use crate::ai::searcher::{Limits, Report, Searcher, SharedState};
+use crate::ai::settings::Settings;
+use chess::{Board, ChessMove};
+
+/// What the opponent would do if the side to move could pass.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Threat {
+    /// The opponent's best move after the pass
+    pub mov: ChessMove,
+    /// Score of the position for the side to move, from the search of its move
+    pub score: i32,
+    /// Score for the side to move after passing and the opponent's best reply
+    pub score_after_pass: i32,
+    /// The opponent's expected line after the pass, starting with `mov`
+    pub pv: Vec<ChessMove>,
+}
+
+impl Threat {
+    /// Evaluation units passing would cost the side to move; large values mean a serious threat.
+    pub fn swing(&self) -> i32 {
+        self.score - self.score_after_pass
+    }
+}
+
+/// Find the threat on `board` with a null move search of the opponent's moves after a pass
+/// within `limits`, compared to `normal`, the report of the search for the move on `board`.
+/// There is no threat to find when the side to move is in check, as it cannot pass,
+/// or when the move was played without a search, as there is no score to compare to.
+pub fn find_threat(
+    searcher: &mut dyn Searcher,
+    state: &SharedState,
+    board: Board,
+    normal: &Report,
+    settings: Settings,
+    limits: Limits,
+) -> Option<Threat> {
+    if normal.depth == 0 {
+        return None;
+    }
+    let passed = board.null_move()?;
+    let state = SharedState {
+        tree: None,
+        ..state.clone()
+    };
+    let limits = Limits {
+        search_moves: Vec::new(),
+        ..limits
+    };
+
+    // The pass makes every earlier position unreachable again
+    let reply = searcher.search(&state, passed, &[], settings, limits, &mut |_| ());
+    let threat = Threat {
+        mov: reply.best,
+        score: normal.score,
+        score_after_pass: -reply.score,
+        pv: reply.pv,
+    };
+    log::info!(
+        "Threat {} after passing, swing {} ({} -> {})",
+        threat.mov,
+        threat.swing(),
+        threat.score,
+        threat.score_after_pass
+    );
+    Some(threat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_threat;
+    use crate::ai::minimax::Pvs;
+    use crate::ai::ordering::Heuristics;
+    use crate::ai::searcher::{Limits, Report, Searcher, SharedState};
+    use crate::ai::settings::Settings;
+    use crate::ai::table::TransTable;
+    use chess::{Board, ChessMove, Square};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn state() -> SharedState {
+        SharedState {
+            table: Arc::new(TransTable::new()),
+            heuristics: Arc::new(Heuristics::new()),
+            tree: None,
+        }
+    }
+
+    #[test]
+    fn test_finds_mate_threat() {
+        // Black threatens Rd1#, which white has to answer by making luft
+        let board = Board::from_str("3r2k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let limits = Limits {
+            time: 0.0,
+            nodes: Some(20000),
+            search_moves: Vec::new(),
+        };
+        let state = state();
+        let settings = Settings::default();
+        let normal = Pvs.search(&state, board, &[], settings, limits.clone(), &mut |_| ());
+        let threat =
+            find_threat(&mut Pvs, &state, board, &normal, settings, limits.clone()).unwrap();
+        assert_eq!(threat.mov, ChessMove::new(Square::D8, Square::D1, None));
+        assert_eq!(threat.pv[0], threat.mov);
+        assert_eq!(threat.score, normal.score);
+        assert!(threat.swing() > 10000);
+
+        // Without a searched score to compare to, there is no threat
+        let unsearched = Report::unsearched(normal.best);
+        let threat = find_threat(&mut Pvs, &state, board, &unsearched, settings, limits);
+        assert_eq!(threat, None);
+    }
+
+    #[test]
+    fn test_no_threat_in_check() {
+        let board = Board::from_str("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").unwrap();
+        let limits = Limits {
+            time: 0.0,
+            nodes: Some(1000),
+            search_moves: Vec::new(),
+        };
+        let state = state();
+        let settings = Settings::default();
+        let normal = Pvs.search(&state, board, &[], settings, limits.clone(), &mut |_| ());
+        let threat = find_threat(&mut Pvs, &state, board, &normal, settings, limits);
+        assert_eq!(threat, None);
+    }
+}
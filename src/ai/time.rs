@@ -0,0 +1,160 @@
+use chess::ChessMove;
+use std::time::Instant;
+
+/// The requested time is a hard limit; a search normally takes this much less,
+/// so unstable searches can be extended up to the full requested time.
+const MAX_EXTENSION: f32 = 1.6;
+/// Never stop a stable search before this fraction of the requested time.
+const MIN_FRACTION: f32 = 0.3;
+/// How much less time each depth with an unchanged best move needs.
+const STABILITY_DISCOUNT: f32 = 0.15;
+/// How much more time is used every time the best move changes.
+const INSTABILITY_FACTOR: f32 = 1.3;
+/// A drop of at least this much between depths counts as a score drop, in the units
+/// of the evaluation (NNUE units, or about centipawns with the classical evaluation)...
+const SCORE_DROP: i32 = 30;
+/// ...and makes the search use this much more time.
+const SCORE_DROP_FACTOR: f32 = 1.5;
+
+/// Decides when to stop iterative deepening, based on how the search develops:
+/// A best move that stays the same for many depths needs less time, while
+/// changing best moves and dropping scores get more time, up to the requested time.
+pub struct TimeManager {
+    start: Instant,
+    /// The requested time per move in seconds, which is never exceeded
+    limit: f32,
+    /// The time a search normally takes, a part of the limit
+    target: f32,
+    /// How much of the target time to use; changes as depths finish
+    factor: f32,
+    /// Number of legal moves at the root
+    legal_moves: usize,
+    best: Option<ChessMove>,
+    /// Depths in a row the best move stayed the same
+    stable_depths: u32,
+    last_score: Option<i32>,
+}
+
+impl TimeManager {
+    pub fn new(limit: f32, legal_moves: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            limit,
+            target: limit / MAX_EXTENSION,
+            factor: 1.0,
+            legal_moves,
+            best: None,
+            stable_depths: 0,
+            last_score: None,
+        }
+    }
+
+    /// A depth was fully searched, with `best` scoring `score`.
+    pub fn depth_finished(&mut self, best: ChessMove, score: i32) {
+        if self.best == Some(best) {
+            self.stable_depths += 1;
+            self.factor = f32::max(MIN_FRACTION, self.factor - STABILITY_DISCOUNT);
+        } else {
+            self.changed_best(best);
+        }
+
+        if let Some(last_score) = self.last_score {
+            if score <= last_score - SCORE_DROP {
+                log::debug!("Score dropped from {} to {}", last_score, score);
+                self.factor = f32::min(MAX_EXTENSION, self.factor * SCORE_DROP_FACTOR);
+            }
+        }
+        self.last_score = Some(score);
+    }
+
+    /// A new best move was found while searching a depth.
+    pub fn best_move_found(&mut self, best: ChessMove) {
+        if self.best != Some(best) {
+            self.changed_best(best);
+        }
+    }
+
+    fn changed_best(&mut self, best: ChessMove) {
+        if self.best.is_some() {
+            self.factor = f32::min(
+                MAX_EXTENSION,
+                f32::max(1.0, self.factor) * INSTABILITY_FACTOR,
+            );
+        }
+        self.best = Some(best);
+        self.stable_depths = 0;
+    }
+
+    pub fn should_stop(&self) -> bool {
+        self.should_stop_after(self.start.elapsed().as_secs_f32())
+    }
+
+    fn should_stop_after(&self, elapsed: f32) -> bool {
+        if elapsed >= self.limit || self.legal_moves == 0 {
+            return true;
+        }
+        match self.best {
+            // Keep searching until there is any move at all
+            None => false,
+            // Nothing to think about
+            Some(_) if self.legal_moves == 1 => true,
+            Some(_) => elapsed >= self.target * self.factor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeManager;
+    use chess::{ChessMove, Square};
+
+    fn mov(to: Square) -> ChessMove {
+        ChessMove::new(Square::E2, to, None)
+    }
+
+    #[test]
+    fn test_stable_stops_early() {
+        let mut time = TimeManager::new(1.6, 20);
+        assert!(!time.should_stop_after(0.9));
+        for _ in 0..5 {
+            time.depth_finished(mov(Square::E4), 10);
+        }
+        assert!(!time.should_stop_after(0.2));
+        assert!(time.should_stop_after(0.5));
+    }
+
+    #[test]
+    fn test_unstable_extends() {
+        let mut time = TimeManager::new(1.6, 20);
+        time.depth_finished(mov(Square::E4), 10);
+        time.best_move_found(mov(Square::E3));
+        time.depth_finished(mov(Square::E3), 20);
+        assert!(!time.should_stop_after(1.1));
+        assert!(time.should_stop_after(1.5));
+    }
+
+    #[test]
+    fn test_score_drop_extends() {
+        let mut time = TimeManager::new(1.6, 20);
+        time.depth_finished(mov(Square::E4), 50);
+        time.depth_finished(mov(Square::E4), -50);
+        assert!(!time.should_stop_after(1.0));
+        // Never beyond the requested time
+        time.depth_finished(mov(Square::E4), -500);
+        time.depth_finished(mov(Square::E4), -5000);
+        time.best_move_found(mov(Square::E3));
+        assert!(!time.should_stop_after(1.5));
+        assert!(time.should_stop_after(1.6));
+    }
+
+    #[test]
+    fn test_only_move_and_no_move() {
+        let mut time = TimeManager::new(1.0, 1);
+        assert!(!time.should_stop_after(0.9));
+        time.depth_finished(mov(Square::E4), 0);
+        assert!(time.should_stop_after(0.0));
+
+        // Checkmate or stalemate: Nothing to search at all
+        assert!(TimeManager::new(1.0, 0).should_stop_after(0.0));
+    }
+}
@@ -0,0 +1,258 @@
+use chess::ChessMove;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Stop recording beyond this many nodes, so a long search cannot use up all memory.
+const MAX_NODES: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// `minimax`, with a full window
+    Pv,
+    /// `scout_search`, with a zero window
+    Scout,
+    /// `explore_captures`
+    Quiescence,
+}
+
+/// Why a node returned before searching all of its moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cutoff {
+    Stopped,
+    Draw,
+    /// The table already had a good enough score
+    Table,
+    Checkmate,
+    Stalemate,
+    ProbCut,
+    MultiCut,
+    /// The static evaluation of a quiescence node was already good enough
+    StandPat,
+    /// The move scored at least beta
+    Beta(ChessMove),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    /// Index of the parent node; root moves have none
+    pub parent: Option<usize>,
+    /// The move leading to this node
+    pub mov: Option<ChessMove>,
+    pub kind: NodeKind,
+    pub depth: i16,
+    pub alpha: i32,
+    pub beta: i32,
+    pub score: i32,
+    pub cutoff: Option<Cutoff>,
+    /// If the table had an entry for the position
+    pub table_hit: bool,
+}
+
+thread_local! {
+    /// Nodes being searched on this thread, innermost last; `None` for nodes not recorded.
+    /// The subtree of a root move is always searched on a single thread.
+    static STACK: RefCell<Vec<Option<usize>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A record of the nodes of a search, up to `max_ply` plies from the root,
+/// to find out why a move was chosen.
+pub struct SearchTree {
+    max_ply: usize,
+    nodes: Mutex<Vec<Node>>,
+}
+
+impl SearchTree {
+    pub fn new(max_ply: usize) -> Self {
+        Self {
+            max_ply,
+            nodes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Forget the nodes of the previous search.
+    pub fn clear(&self) {
+        self.nodes.lock().unwrap().clear();
+    }
+
+    pub fn nodes(&self) -> Vec<Node> {
+        self.nodes.lock().unwrap().clone()
+    }
+
+    /// Record a node around `search`, which returns its score.
+    pub(super) fn record(
+        &self,
+        kind: NodeKind,
+        mov: Option<ChessMove>,
+        depth: i16,
+        alpha: i32,
+        beta: i32,
+        search: impl FnOnce() -> i32,
+    ) -> i32 {
+        let id = STACK.with(|stack| {
+            let stack = stack.borrow();
+            let parent = match stack.last() {
+                None => None,
+                Some(Some(parent)) if stack.len() < self.max_ply => Some(*parent),
+                Some(_) => return None,
+            };
+            let mut nodes = self.nodes.lock().unwrap();
+            if nodes.len() >= MAX_NODES || self.max_ply == 0 {
+                return None;
+            }
+            nodes.push(Node {
+                parent,
+                mov,
+                kind,
+                depth,
+                alpha,
+                beta,
+                score: 0,
+                cutoff: None,
+                table_hit: false,
+            });
+            Some(nodes.len() - 1)
+        });
+
+        STACK.with(|stack| stack.borrow_mut().push(id));
+        let score = search();
+        STACK.with(|stack| stack.borrow_mut().pop());
+
+        if let Some(id) = id {
+            self.nodes.lock().unwrap()[id].score = score;
+        }
+        score
+    }
+
+    pub(super) fn cutoff(&self, cutoff: Cutoff) {
+        self.update(|node| node.cutoff = Some(cutoff));
+    }
+
+    pub(super) fn table_hit(&self) {
+        self.update(|node| node.table_hit = true);
+    }
+
+    /// Change the node currently searched on this thread, if it is recorded.
+    fn update(&self, f: impl FnOnce(&mut Node)) {
+        if let Some(Some(id)) = STACK.with(|stack| stack.borrow().last().copied()) {
+            f(&mut self.nodes.lock().unwrap()[id]);
+        }
+    }
+
+    /// All nodes as a JSON array, each with its index and the index of its parent.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[\n");
+        let nodes = self.nodes.lock().unwrap();
+        for (id, node) in nodes.iter().enumerate() {
+            let separator = if id + 1 < nodes.len() { "," } else { "" };
+            writeln!(
+                json,
+                "  {{\"id\": {}, \"parent\": {}, \"move\": {}, \"kind\": \"{}\", \"depth\": {}, \
+                 \"alpha\": {}, \"beta\": {}, \"score\": {}, \"cutoff\": {}, \"table_hit\": {}}}{}",
+                id,
+                node.parent.map_or("null".to_string(), |p| p.to_string()),
+                node.mov
+                    .map_or("null".to_string(), |m| format!("\"{}\"", m)),
+                kind_name(node.kind),
+                node.depth,
+                node.alpha,
+                node.beta,
+                node.score,
+                node.cutoff
+                    .map_or("null".to_string(), |c| format!("\"{}\"", cutoff_name(c))),
+                node.table_hit,
+                separator
+            )
+            .unwrap();
+        }
+        json.push(']');
+        json
+    }
+
+    /// All nodes as a Graphviz graph, with the root moves below a single root node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph search {\n  node [shape=box, fontname=monospace];\n");
+        dot.push_str("  root;\n");
+        for (id, node) in self.nodes.lock().unwrap().iter().enumerate() {
+            writeln!(
+                dot,
+                "  n{} [label=\"{} {} d={}\\n[{}, {}] -> {}{}{}\"];",
+                id,
+                node.mov.map_or("-".to_string(), |m| m.to_string()),
+                kind_name(node.kind),
+                node.depth,
+                node.alpha,
+                node.beta,
+                node.score,
+                node.cutoff
+                    .map_or(String::new(), |c| format!("\\n{}", cutoff_name(c))),
+                if node.table_hit { "\\nTT" } else { "" }
+            )
+            .unwrap();
+            match node.parent {
+                Some(parent) => writeln!(dot, "  n{} -> n{};", parent, id).unwrap(),
+                None => writeln!(dot, "  root -> n{};", id).unwrap(),
+            }
+        }
+        dot.push('}');
+        dot
+    }
+}
+
+fn kind_name(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Pv => "pv",
+        NodeKind::Scout => "scout",
+        NodeKind::Quiescence => "quiescence",
+    }
+}
+
+fn cutoff_name(cutoff: Cutoff) -> String {
+    match cutoff {
+        Cutoff::Stopped => "stopped".to_string(),
+        Cutoff::Draw => "draw".to_string(),
+        Cutoff::Table => "table".to_string(),
+        Cutoff::Checkmate => "checkmate".to_string(),
+        Cutoff::Stalemate => "stalemate".to_string(),
+        Cutoff::ProbCut => "probcut".to_string(),
+        Cutoff::MultiCut => "multi-cut".to_string(),
+        Cutoff::StandPat => "stand pat".to_string(),
+        Cutoff::Beta(mov) => format!("beta {}", mov),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cutoff, NodeKind, SearchTree};
+    use chess::{ChessMove, Square};
+
+    #[test]
+    fn test_record() {
+        let tree = SearchTree::new(2);
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        let e5 = ChessMove::new(Square::E7, Square::E5, None);
+        let score = tree.record(NodeKind::Pv, Some(e4), 3, -10, 10, || {
+            tree.table_hit();
+            -tree.record(NodeKind::Scout, Some(e5), 2, -1, 0, || {
+                tree.cutoff(Cutoff::Beta(e5));
+                // Beyond the maximum ply
+                tree.record(NodeKind::Quiescence, None, 0, 0, 1, || 5);
+                tree.cutoff(Cutoff::Beta(e5));
+                3
+            })
+        });
+        assert_eq!(score, -3);
+
+        let nodes = tree.nodes();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].parent, None);
+        assert!(nodes[0].table_hit);
+        assert_eq!(nodes[0].score, -3);
+        assert_eq!(nodes[1].parent, Some(0));
+        assert_eq!(nodes[1].cutoff, Some(Cutoff::Beta(e5)));
+        assert_eq!(nodes[1].score, 3);
+
+        assert!(tree.to_json().contains("\"cutoff\": \"beta e7e5\""));
+        assert!(tree.to_dot().contains("n0 -> n1;"));
+    }
+}
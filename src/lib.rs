@@ -0,0 +1,8 @@
+//! A chess engine: searchers, evaluation and the state they share, so other crates can
+//! drive them or plug in their own [`ai::Searcher`].
+
+#![feature(test)]
+
+extern crate test;
+
+pub mod ai;
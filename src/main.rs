@@ -1,20 +1,24 @@
-#![feature(test)]
-
-extern crate test;
-
-use chess::Board;
+use chess::{Board, ChessMove};
+use chevii::ai;
 use rayon::ThreadPoolBuilder;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
 use std::process;
 use std::str::FromStr;
 use structopt::StructOpt;
 
-pub mod ai;
+/// Share of the time per move spent on the threat with `--threats`
+const THREAT_SHARE: f32 = 0.25;
 
 #[derive(StructOpt, Debug)]
 struct Opt {
-    /// Produce a single move
-    #[structopt(short, long)]
-    position: String,
+    /// Produce a single move. If not given, commands are read from stdin instead:
+    /// `go <time> <fen>` to produce a move, `newgame` to forget the previous game,
+    /// `contempt <white> <black>` to change the contempt
+    #[structopt(short, long, parse(try_from_str = Board::from_str))]
+    position: Option<Board>,
 
     /// Number of threads to use
     #[structopt(long, default_value = "8")]
@@ -23,6 +27,114 @@ struct Opt {
     /// Time for thinking per move
     #[structopt(short, long, default_value = "3")]
     time: f32,
+
+    /// Transposition table file; loaded on start if it exists and saved on exit
+    #[structopt(long, parse(from_os_str))]
+    table: Option<PathBuf>,
+
+    /// Additional transposition table files to merge in on start
+    #[structopt(long, parse(from_os_str))]
+    merge: Vec<PathBuf>,
+
+    /// How much worse than equal a draw is when playing white, in evaluation units;
+    /// negative to seek draws
+    #[structopt(long, default_value = "0", allow_hyphen_values = true)]
+    contempt_white: i32,
+
+    /// How much worse than equal a draw is when playing black, in evaluation units;
+    /// negative to seek draws
+    #[structopt(long, default_value = "0", allow_hyphen_values = true)]
+    contempt_black: i32,
+
+    /// Enable ProbCut forward pruning
+    #[structopt(long)]
+    probcut: bool,
+
+    /// How much a shallow capture search has to beat beta by for ProbCut, in evaluation units
+    #[structopt(long, default_value = "100")]
+    probcut_margin: i32,
+
+    /// How much shallower the ProbCut search is, in plies
+    #[structopt(long, default_value = "4")]
+    probcut_reduction: i16,
+
+    /// Enable multi-cut forward pruning
+    #[structopt(long)]
+    multi_cut: bool,
+
+    /// Always search, even when there is only one move or a mate in one, e.g. for analysis
+    #[structopt(long)]
+    no_shortcuts: bool,
+
+    /// Search this many nodes (playouts with mcts) per move instead of for `--time`;
+    /// with pvs on a single thread, so the same input always gives the same move
+    #[structopt(long)]
+    nodes: Option<u64>,
+
+    /// Position evaluation: the NNUE network from `model.nnue`, or the classical evaluation
+    /// with piece-square tables; NNUE falls back to classical if the network is missing
+    #[structopt(long, default_value = "nnue", possible_values = &["nnue", "classical"])]
+    eval: String,
+
+    /// Search algorithm: principal variation search, MTD(f) or Monte Carlo tree search
+    #[structopt(long, default_value = "pvs", possible_values = &["pvs", "mtdf", "mcts"])]
+    searcher: String,
+
+    /// Print the progress of each search as `info` lines, like the result of each root move.
+    /// Workers do not report their progress, so not with `--coordinator`
+    #[structopt(long, conflicts_with = "coordinator")]
+    info: bool,
+
+    /// With each move, also search what the opponent would play if it was its turn,
+    /// within the same time, and print it with how much it would gain as `threat <move> <swing>`.
+    /// Not with `--coordinator`
+    #[structopt(long, conflicts_with = "coordinator")]
+    threats: bool,
+
+    /// Instead of playing, find the shortest mate in at most this many moves
+    /// for the side to move in `--position`
+    #[structopt(long, requires = "position")]
+    mate: Option<u16>,
+
+    /// Instead of searching, wait for `--workers` worker processes to connect to this address,
+    /// e.g. `127.0.0.1:7878`, and let them search a share of the root moves each
+    #[structopt(long)]
+    coordinator: Option<String>,
+
+    /// Number of workers the coordinator waits for
+    #[structopt(long, default_value = "2")]
+    workers: usize,
+
+    /// Connect to the coordinator at this address and search for it, using the other options
+    #[structopt(long)]
+    worker: Option<String>,
+
+    /// Write the search tree of each move to this file as JSON, for debugging
+    #[structopt(long, parse(from_os_str))]
+    tree_json: Option<PathBuf>,
+
+    /// Write the search tree of each move to this file as a Graphviz graph, for debugging
+    #[structopt(long, parse(from_os_str))]
+    tree_dot: Option<PathBuf>,
+
+    /// How many plies from the root the written search tree goes
+    #[structopt(long, default_value = "3")]
+    tree_ply: usize,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Print all solutions of a chess problem, with its cooks and duals
+    Solve {
+        /// The problem position
+        #[structopt(name = "fen", parse(try_from_str = Board::from_str))]
+        board: Board,
+        /// `h#n` for a helpmate or `s#n` for a selfmate in `n` moves
+        stipulation: ai::Stipulation,
+    },
 }
 
 fn main() {
@@ -33,8 +145,295 @@ fn main() {
         .build_global()
         .unwrap();
 
-    let board = Board::from_str(&opts.position).unwrap();
-    let mov = ai::get_best_move(board, opts.time);
-    println!("{}", mov);
+    if let Some(Command::Solve { board, stipulation }) = &opts.command {
+        solve_problem(board, *stipulation);
+        process::exit(0);
+    }
+
+    if let Some(moves) = opts.mate {
+        if let Some(board) = opts.position {
+            solve_mate(board, moves);
+        }
+        process::exit(0);
+    }
+
+    let contempt = ai::Contempt {
+        white: opts.contempt_white,
+        black: opts.contempt_black,
+    };
+    if let Some(address) = &opts.coordinator {
+        let listener = TcpListener::bind(address).unwrap_or_else(|error| {
+            eprintln!("Could not listen on {}: {}", address, error);
+            process::exit(1);
+        });
+        let mut coordinator =
+            ai::Coordinator::accept(&listener, opts.workers).unwrap_or_else(lost_workers);
+        Player::set_contempt(&mut coordinator, contempt);
+        run(&mut coordinator, &opts);
+        process::exit(0);
+    }
+
+    let mut engine = ai::Engine::new();
+    let settings = engine.settings_mut();
+    settings.contempt = contempt;
+    if opts.probcut {
+        settings.probcut = Some(ai::ProbCut {
+            margin: opts.probcut_margin,
+            reduction: opts.probcut_reduction,
+            ..ai::ProbCut::default()
+        });
+    }
+    if opts.multi_cut {
+        settings.multi_cut = Some(ai::MultiCut::default());
+    }
+    settings.shortcuts = !opts.no_shortcuts;
+    settings.deterministic = opts.nodes.map(|nodes| ai::Deterministic { nodes });
+    settings.evaluator = match opts.eval.as_str() {
+        "classical" => ai::Evaluator::Classical,
+        _ => ai::Evaluator::Nnue,
+    };
+    if let Some(path) = opts.table.as_ref().filter(|p| p.exists()) {
+        if let Err(error) = engine.load_table(path) {
+            eprintln!("Could not load {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+    for path in &opts.merge {
+        if let Err(error) = engine.merge_table(path) {
+            eprintln!("Could not merge {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+    match opts.searcher.as_str() {
+        "mtdf" => engine.set_searcher(Box::new(ai::Mtdf)),
+        "mcts" => engine.set_searcher(Box::new(ai::Mcts)),
+        _ => engine.set_searcher(Box::new(ai::Pvs)),
+    }
+    if opts.tree_json.is_some() || opts.tree_dot.is_some() {
+        engine.record_tree(opts.tree_ply);
+    }
+
+    match &opts.worker {
+        Some(address) => ai::run_worker(address, &mut engine).unwrap(),
+        None => run(&mut engine, &opts),
+    }
+
+    if let Some(path) = &opts.table {
+        if let Err(error) = engine.save_table(path) {
+            eprintln!("Could not save {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
     process::exit(0);
 }
+
+/// Print the shortest mate with the best defence, if there is one.
+fn solve_mate(board: Board, moves: u16) {
+    let mut solver = ai::MateSolver::new(&board);
+    match solver.solve(&board, moves) {
+        Some(line) => println!(
+            "mate in {}: {}",
+            line.len().div_ceil(2),
+            line.iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        None => println!("no mate in {}", moves),
+    }
+    log::info!("Searched {} nodes", solver.nodes());
+}
+
+/// Print every solution of a helpmate or selfmate, and what makes it unsound.
+fn solve_problem(board: &Board, stipulation: ai::Stipulation) {
+    let mut solver = ai::ProblemSolver::new();
+    let solutions = solver.solve(board, stipulation);
+    let line = |moves: &[ChessMove]| {
+        moves
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    if solutions.lines.is_empty() {
+        println!("{}: no solution", stipulation);
+    } else if solutions.is_cooked() {
+        println!("{}: cooked, {} keys", stipulation, solutions.keys.len());
+    } else {
+        println!("{}: unique key {}", stipulation, solutions.keys[0]);
+    }
+    for moves in &solutions.lines {
+        println!("  {}", line(moves));
+    }
+    for moves in &solutions.duals {
+        println!("dual after {}", line(moves));
+    }
+    log::info!("Searched {} nodes", solver.nodes());
+}
+
+/// Where moves come from: the engine of this process, or workers in other processes.
+trait Player {
+    fn play(&mut self, board: Board, time: f32, opts: &Opt) -> ChessMove;
+    fn new_game(&mut self);
+    fn set_contempt(&mut self, contempt: ai::Contempt);
+}
+
+impl Player for ai::Engine {
+    fn play(&mut self, board: Board, time: f32, opts: &Opt) -> ChessMove {
+        let share = if opts.threats {
+            1.0 - THREAT_SHARE
+        } else {
+            1.0
+        };
+        let report = search(self, board, limits(time, share, opts), opts);
+        write_tree(self, opts);
+        if opts.threats {
+            print_threat(self, board, &report, limits(time, THREAT_SHARE, opts));
+        }
+        report.best
+    }
+
+    fn new_game(&mut self) {
+        ai::Engine::new_game(self);
+    }
+
+    fn set_contempt(&mut self, contempt: ai::Contempt) {
+        self.settings_mut().contempt = contempt;
+    }
+}
+
+impl Player for ai::Coordinator {
+    fn play(&mut self, board: Board, time: f32, opts: &Opt) -> ChessMove {
+        let limits = ai::Limits {
+            time,
+            nodes: opts.nodes,
+            search_moves: Vec::new(),
+        };
+        self.search(board, &limits)
+            .unwrap_or_else(lost_workers)
+            .best
+    }
+
+    fn new_game(&mut self) {
+        ai::Coordinator::new_game(self).unwrap_or_else(lost_workers);
+    }
+
+    fn set_contempt(&mut self, contempt: ai::Contempt) {
+        ai::Coordinator::set_contempt(self, contempt).unwrap_or_else(lost_workers);
+    }
+}
+
+/// A worker disconnected or stopped answering, so the coordinator cannot go on.
+fn lost_workers<T>(error: io::Error) -> T {
+    eprintln!("Lost a worker: {}", error);
+    process::exit(1);
+}
+
+/// Play the move for `--position`, or a session of moves read from stdin.
+fn run(player: &mut impl Player, opts: &Opt) {
+    match opts.position {
+        Some(board) => {
+            println!("{}", player.play(board, opts.time, opts));
+        }
+        None => run_session(player, opts),
+    }
+}
+
+/// Play several moves with the same player, keeping its state between them.
+fn run_session(player: &mut impl Player, opts: &Opt) {
+    for line in io::stdin().lock().lines() {
+        let line = line.unwrap();
+        let mut parts = line.trim().splitn(3, ' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("go"), Some(time), Some(fen)) => match (time.parse(), Board::from_str(fen)) {
+                (Ok(time), Ok(board)) => {
+                    let mov = player.play(board, time, opts);
+                    println!("{}", mov);
+                    io::stdout().flush().unwrap();
+                }
+                _ => log::warn!("Invalid time or position: {}", line),
+            },
+            (Some("newgame"), None, None) => player.new_game(),
+            (Some("contempt"), Some(white), Some(black)) => match (white.parse(), black.parse()) {
+                (Ok(white), Ok(black)) => player.set_contempt(ai::Contempt { white, black }),
+                _ => log::warn!("Invalid contempt: {}", line),
+            },
+            (Some("quit"), None, None) => break,
+            _ => log::warn!("Unknown command: {}", line),
+        }
+    }
+}
+
+/// Find the best move on `board`, printing the progress if `--info` is given.
+fn search(engine: &mut ai::Engine, board: Board, limits: ai::Limits, opts: &Opt) -> ai::Report {
+    if opts.info {
+        engine.search(board, limits, &mut print_info)
+    } else {
+        engine.search(board, limits, &mut |_| ())
+    }
+}
+
+/// The `share` of the time or nodes per move.
+fn limits(time: f32, share: f32, opts: &Opt) -> ai::Limits {
+    ai::Limits {
+        time: time * share,
+        nodes: opts.nodes.map(|nodes| (nodes as f32 * share) as u64),
+        search_moves: Vec::new(),
+    }
+}
+
+fn print_info(info: &ai::Info) {
+    let line = |moves: &[ChessMove]| {
+        moves
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    match info {
+        ai::Info::BestMove { depth, mov, score } => {
+            println!("info depth {} score cp {} currbest {}", depth, score, mov)
+        }
+        ai::Info::Depth { depth, best, score } => {
+            println!("info depth {} score cp {} bestmove {}", depth, score, best)
+        }
+        ai::Info::CurrentMove { depth, mov, number } => println!(
+            "info depth {} currmove {} currmovenumber {}",
+            depth, mov, number
+        ),
+        ai::Info::RootMove(root_move) => println!(
+            "info depth {} move {} score cp {}{} nodes {} pv {}",
+            root_move.depth,
+            root_move.mov,
+            root_move.score,
+            match root_move.bound {
+                ai::Bound::Exact => "",
+                ai::Bound::Lower => " lowerbound",
+                ai::Bound::Upper => " upperbound",
+            },
+            root_move.nodes,
+            line(&root_move.pv)
+        ),
+    }
+}
+
+/// Print the opponent's threat on `board`, if it is not in check, compared to the `report`
+/// of the search for the move.
+fn print_threat(engine: &mut ai::Engine, board: Board, report: &ai::Report, limits: ai::Limits) {
+    if let Some(threat) = engine.threat(board, report, limits) {
+        println!("threat {} {}", threat.mov, threat.swing());
+    }
+}
+
+/// Write the tree of the last search to the files given on the command line.
+fn write_tree(engine: &ai::Engine, opts: &Opt) {
+    if let Some(tree) = engine.tree() {
+        if let Some(path) = &opts.tree_json {
+            fs::write(path, tree.to_json()).unwrap();
+        }
+        if let Some(path) = &opts.tree_dot {
+            fs::write(path, tree.to_dot()).unwrap();
+        }
+    }
+}
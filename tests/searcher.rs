@@ -0,0 +1,69 @@
+use chess::{Board, BoardStatus, ChessMove, MoveGen, Square};
+use chevii::ai::{Bound, Engine, Entry, Info, Limits, Report, Searcher, Settings, SharedState};
+use std::str::FromStr;
+
+/// Plays a mate in one if there is one, and the first legal move otherwise.
+struct MateInOne;
+
+impl Searcher for MateInOne {
+    fn name(&self) -> &'static str {
+        "mate in one"
+    }
+
+    fn search(
+        &mut self,
+        state: &SharedState,
+        board: Board,
+        _history: &[u64],
+        _settings: Settings,
+        limits: Limits,
+        info: &mut dyn FnMut(&Info),
+    ) -> Report {
+        let moves = MoveGen::new_legal(&board).filter(|m| limits.allows(*m));
+        let mut report = Report::unsearched(ChessMove::default());
+        for mov in moves {
+            let mate = board.make_move_new(mov).status() == BoardStatus::Checkmate;
+            if report.best == ChessMove::default() || mate {
+                report.best = mov;
+                report.depth = 1;
+                info(&Info::BestMove {
+                    depth: 1,
+                    mov,
+                    score: 0,
+                });
+            }
+            if mate {
+                break;
+            }
+        }
+        state.table.put(Entry {
+            zobrist: board.get_hash(),
+            score: 0,
+            depth_of_score: 1,
+            depth_of_search: 1,
+            best_move: Some(report.best),
+            bound: Bound::Lower,
+        });
+        state.heuristics.record_cutoff(&board, report.best, 0, 1);
+        report
+    }
+}
+
+#[test]
+fn test_external_searcher() {
+    let board = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+    let mut engine = Engine::new();
+    engine.set_searcher(Box::new(MateInOne));
+    // The engine would play the mate without asking the searcher
+    engine.settings_mut().shortcuts = false;
+    let limits = Limits {
+        time: 0.0,
+        nodes: None,
+        search_moves: Vec::new(),
+    };
+    let mut published = Vec::new();
+    let report = engine.search(board, limits, &mut |info| published.push(info.clone()));
+    let mate = ChessMove::new(Square::D1, Square::D8, None);
+    assert_eq!(report.best, mate);
+    assert!(matches!(published.last(), Some(Info::BestMove { mov, .. }) if *mov == mate));
+}